@@ -1,5 +1,6 @@
 CREATE TABLE
     IF NOT EXISTS pools (
+        chain_id INT NOT NULL,
         protocol TEXT NOT NULL,
         factory TEXT NOT NULL,
         pool TEXT NOT NULL,
@@ -9,5 +10,90 @@ CREATE TABLE
         token0_liquidity INT NOT NULL,
         token1_liquidity INT NOT NULL,
         timestamp INT NOT NULL,
-        PRIMARY KEY (pool)
-    );
\ No newline at end of file
+        PRIMARY KEY (chain_id, pool)
+    );
+
+CREATE TABLE
+    IF NOT EXISTS scan_cursor (
+        chain_id INT NOT NULL,
+        blocknumber INT NOT NULL,
+        blockhash TEXT NOT NULL,
+        timestamp INT NOT NULL,
+        PRIMARY KEY (chain_id)
+    );
+
+CREATE TABLE
+    IF NOT EXISTS tokens (
+        chain_id INT NOT NULL,
+        token TEXT NOT NULL,
+        name TEXT NOT NULL,
+        symbol TEXT NOT NULL,
+        decimals INT NOT NULL,
+        total_supply TEXT NOT NULL,
+        timestamp INT NOT NULL,
+        PRIMARY KEY (chain_id, token)
+    );
+
+CREATE TABLE
+    IF NOT EXISTS swaps (
+        chain_id INT NOT NULL,
+        blocknumber INT NOT NULL,
+        tx_hash TEXT NOT NULL,
+        log_index INT NOT NULL,
+        pool TEXT NOT NULL,
+        amount0 TEXT NOT NULL,
+        amount1 TEXT NOT NULL,
+        sqrt_price_x96 TEXT,
+        tick INT,
+        liquidity TEXT,
+        usd_value REAL NOT NULL,
+        sender TEXT NOT NULL,
+        recipient TEXT NOT NULL,
+        timestamp INT NOT NULL,
+        tx_from TEXT NOT NULL DEFAULT '',
+        category TEXT NOT NULL DEFAULT 'retail',
+        PRIMARY KEY (chain_id, tx_hash, log_index)
+    );
+
+CREATE INDEX IF NOT EXISTS swaps_pool_timestamp ON swaps (chain_id, pool, timestamp);
+
+CREATE INDEX IF NOT EXISTS swaps_blocknumber ON swaps (chain_id, blocknumber);
+
+CREATE TABLE
+    IF NOT EXISTS liquidity_events (
+        chain_id INT NOT NULL,
+        blocknumber INT NOT NULL,
+        tx_hash TEXT NOT NULL,
+        log_index INT NOT NULL,
+        pool TEXT NOT NULL,
+        event TEXT NOT NULL,
+        owner TEXT NOT NULL,
+        tick_lower INT NOT NULL,
+        tick_upper INT NOT NULL,
+        liquidity TEXT NOT NULL,
+        amount0 TEXT NOT NULL,
+        amount1 TEXT NOT NULL,
+        usd_value REAL NOT NULL,
+        timestamp INT NOT NULL,
+        PRIMARY KEY (chain_id, tx_hash, log_index)
+    );
+
+CREATE INDEX IF NOT EXISTS liquidity_events_pool_blocknumber ON liquidity_events (chain_id, pool, blocknumber);
+
+CREATE TABLE
+    IF NOT EXISTS pool_created (
+        chain_id INT NOT NULL,
+        protocol TEXT NOT NULL,
+        factory TEXT NOT NULL,
+        pool TEXT NOT NULL,
+        token0 TEXT NOT NULL,
+        token1 TEXT NOT NULL,
+        fee INT NOT NULL,
+        tick_spacing INT NOT NULL,
+        blocknumber INT NOT NULL,
+        tx_hash TEXT NOT NULL,
+        timestamp INT NOT NULL,
+        PRIMARY KEY (chain_id, pool)
+    );
+
+CREATE INDEX IF NOT EXISTS pool_created_blocknumber ON pool_created (chain_id, blocknumber);
\ No newline at end of file
@@ -1,14 +1,198 @@
-use crate::api::HttpResponseExt;
+use crate::{
+    api::HttpResponseExt,
+    blockchain::ethereum::HexParseTrait,
+    position_simulator::{self, SimulateRequest},
+    yield_scaner::{FeedFilter, V3ScanWorker, YieldFilter},
+};
 use actix_web::{
-    HttpResponse, Responder, get,
+    HttpResponse, Responder, get, post,
     web::{self},
 };
+use std::sync::Arc;
+use web3::types::Address;
 
 pub fn register(config: &mut web::ServiceConfig) {
-    config.service(status);
+    config
+        .service(status)
+        .service(chains)
+        .service(pools_top)
+        .service(pools_new)
+        .service(pool_liquidity_events)
+        .service(pool_detail)
+        .service(token_detail)
+        .service(stables)
+        .service(simulate)
+        .service(chain_pools_top)
+        .service(chain_pools_new)
+        .service(chain_pool_liquidity_events)
+        .service(chain_pool_detail)
+        .service(chain_token_detail)
+        .service(chain_stables)
+        .service(chain_simulate);
 }
 
 #[get("/status")]
 async fn status() -> impl Responder {
     HttpResponse::response_data("OK")
 }
+
+#[get("/chains")]
+async fn chains() -> impl Responder {
+    HttpResponse::response_data(V3ScanWorker::get_chains())
+}
+
+#[get("/pools/top")]
+async fn pools_top(query: web::Query<YieldFilter>) -> impl Responder {
+    response_pools_top(V3ScanWorker::get_default_worker(), &query)
+}
+
+#[get("/pools/{address}")]
+async fn pool_detail(path: web::Path<String>) -> impl Responder {
+    response_pool_detail(V3ScanWorker::get_default_worker(), &path).await
+}
+
+#[get("/pools/new")]
+async fn pools_new(query: web::Query<FeedFilter>) -> impl Responder {
+    response_pools_new(V3ScanWorker::get_default_worker(), &query).await
+}
+
+#[get("/pools/{address}/liquidity")]
+async fn pool_liquidity_events(path: web::Path<String>, query: web::Query<FeedFilter>) -> impl Responder {
+    response_pool_liquidity_events(V3ScanWorker::get_default_worker(), &path, &query).await
+}
+
+#[get("/tokens/{address}")]
+async fn token_detail(path: web::Path<String>) -> impl Responder {
+    response_token_detail(V3ScanWorker::get_default_worker(), &path)
+}
+
+#[get("/stables")]
+async fn stables() -> impl Responder {
+    response_stables(V3ScanWorker::get_default_worker())
+}
+
+#[post("/simulate")]
+async fn simulate(body: web::Json<SimulateRequest>) -> impl Responder {
+    response_simulate(V3ScanWorker::get_default_worker(), &body)
+}
+
+#[get("/chains/{chain_id}/pools/top")]
+async fn chain_pools_top(path: web::Path<u64>, query: web::Query<YieldFilter>) -> impl Responder {
+    response_pools_top(V3ScanWorker::get_worker(*path), &query)
+}
+
+#[get("/chains/{chain_id}/pools/{address}")]
+async fn chain_pool_detail(path: web::Path<(u64, String)>) -> impl Responder {
+    response_pool_detail(V3ScanWorker::get_worker(path.0), &path.1).await
+}
+
+#[get("/chains/{chain_id}/pools/new")]
+async fn chain_pools_new(path: web::Path<u64>, query: web::Query<FeedFilter>) -> impl Responder {
+    response_pools_new(V3ScanWorker::get_worker(*path), &query).await
+}
+
+#[get("/chains/{chain_id}/pools/{address}/liquidity")]
+async fn chain_pool_liquidity_events(path: web::Path<(u64, String)>, query: web::Query<FeedFilter>) -> impl Responder {
+    response_pool_liquidity_events(V3ScanWorker::get_worker(path.0), &path.1, &query).await
+}
+
+#[get("/chains/{chain_id}/tokens/{address}")]
+async fn chain_token_detail(path: web::Path<(u64, String)>) -> impl Responder {
+    response_token_detail(V3ScanWorker::get_worker(path.0), &path.1)
+}
+
+#[get("/chains/{chain_id}/stables")]
+async fn chain_stables(path: web::Path<u64>) -> impl Responder {
+    response_stables(V3ScanWorker::get_worker(*path))
+}
+
+#[post("/chains/{chain_id}/simulate")]
+async fn chain_simulate(path: web::Path<u64>, body: web::Json<SimulateRequest>) -> impl Responder {
+    response_simulate(V3ScanWorker::get_worker(*path), &body)
+}
+
+fn response_pools_top(worker: Option<Arc<V3ScanWorker>>, filter: &YieldFilter) -> HttpResponse {
+    let Some(worker) = worker else {
+        return HttpResponse::response_error("unknown chain");
+    };
+
+    match worker.get_top_pools(filter) {
+        Ok(pools) => HttpResponse::response_data(pools),
+        Err(e) => HttpResponse::response_error(&e.to_string()),
+    }
+}
+
+async fn response_pool_detail(worker: Option<Arc<V3ScanWorker>>, address: &str) -> HttpResponse {
+    let Some(worker) = worker else {
+        return HttpResponse::response_error("unknown chain");
+    };
+    let Ok(pool) = address.parse::<Address>() else {
+        return HttpResponse::response_error("invalid address");
+    };
+
+    match worker.get_pool_detail(pool).await {
+        Ok(Some(detail)) => HttpResponse::response_data(detail),
+        Ok(None) => HttpResponse::response_error_notfound(),
+        Err(e) => HttpResponse::response_error(&e.to_string()),
+    }
+}
+
+async fn response_pools_new(worker: Option<Arc<V3ScanWorker>>, filter: &FeedFilter) -> HttpResponse {
+    let Some(worker) = worker else {
+        return HttpResponse::response_error("unknown chain");
+    };
+
+    match worker.get_created_pools(filter.limit).await {
+        Ok(pools) => HttpResponse::response_data(pools),
+        Err(e) => HttpResponse::response_error(&e.to_string()),
+    }
+}
+
+async fn response_pool_liquidity_events(worker: Option<Arc<V3ScanWorker>>, address: &str, filter: &FeedFilter) -> HttpResponse {
+    let Some(worker) = worker else {
+        return HttpResponse::response_error("unknown chain");
+    };
+    let Ok(pool) = address.parse::<Address>() else {
+        return HttpResponse::response_error("invalid address");
+    };
+
+    match worker.get_liquidity_events(pool, filter.limit).await {
+        Ok(events) => HttpResponse::response_data(events),
+        Err(e) => HttpResponse::response_error(&e.to_string()),
+    }
+}
+
+/// Tokens are registered when a pool trading them is discovered, unknown ones are not looked up
+fn response_token_detail(worker: Option<Arc<V3ScanWorker>>, address: &str) -> HttpResponse {
+    let Some(worker) = worker else {
+        return HttpResponse::response_error("unknown chain");
+    };
+    let Ok(token) = address.parse::<Address>() else {
+        return HttpResponse::response_error("invalid address");
+    };
+
+    match worker.get_cached_token_info(&token.to_hex_string()) {
+        Some(token_info) => HttpResponse::response_data(token_info),
+        None => HttpResponse::response_error_notfound(),
+    }
+}
+
+fn response_stables(worker: Option<Arc<V3ScanWorker>>) -> HttpResponse {
+    let Some(worker) = worker else {
+        return HttpResponse::response_error("unknown chain");
+    };
+
+    HttpResponse::response_data(worker.oracle.get_stable_pegs())
+}
+
+fn response_simulate(worker: Option<Arc<V3ScanWorker>>, request: &SimulateRequest) -> HttpResponse {
+    let Some(worker) = worker else {
+        return HttpResponse::response_error("unknown chain");
+    };
+
+    match position_simulator::simulate(&worker, request) {
+        Ok(Some(result)) => HttpResponse::response_data(result),
+        Ok(None) => HttpResponse::response_error_notfound(),
+        Err(e) => HttpResponse::response_error(&e.to_string()),
+    }
+}
@@ -5,6 +5,12 @@ use actix_web::{App, HttpResponse, HttpServer, Responder, get, middleware, web};
 use log::info;
 use std::sync::Arc;
 
+impl Default for APIState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl APIState {
     pub fn new() -> Self {
         APIState {}
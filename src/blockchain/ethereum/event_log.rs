@@ -18,11 +18,7 @@ impl Web3LogEvent for web3::types::Log {
             data: self.data.0.clone(),
         };
 
-        if let Ok(decode_log) = event.parse_log(raw_log) {
-            Some(decode_log)
-        } else {
-            None
-        }
+        event.parse_log(raw_log).ok()
     }
 }
 
@@ -1,5 +1,6 @@
 pub mod event_log;
 pub mod traits;
+pub mod uniswapv2;
 pub mod uniswapv3;
 pub mod web3_reqwest;
 pub mod web3ex;
@@ -9,14 +10,42 @@ use web3_reqwest::ReqwestTransport;
 pub use web3ex::*;
 
 pub const ETH_DECIMALS: u64 = 18;
+/// 10^77 is the largest power of ten a U256 holds
+pub const MAX_TOKEN_DECIMALS: u64 = 77;
 
 pub type Web3Client = web3::Web3<ReqwestTransport>;
 
 pub fn init_web3_http(url: &str) -> Web3Client {
     assert!(url.starts_with("http"));
     let transport = ReqwestTransport::new(url);
-    let web3 = web3::Web3::new(transport);
-    web3
+    web3::Web3::new(transport)
+}
+
+/// Whether a contract call never got an answer from the node, as opposed to a revert or undecodable data
+pub fn is_transport_error(error: &web3::contract::Error) -> bool {
+    matches!(error, web3::contract::Error::Api(error) if is_web3_transport_error(error))
+}
+
+/// Whether a request never got an answer from the node, as opposed to an error or undecodable result
+pub fn is_web3_transport_error(error: &web3::Error) -> bool {
+    matches!(error, web3::Error::Transport(_) | web3::Error::Unreachable)
+}
+
+/// Whether the node refused a log query for spanning too many blocks or matching too many logs.
+/// Nodes word it differently, e.g. "block range is too wide" or "query returned more than 10000 results".
+pub fn is_log_range_error(error: &web3::Error) -> bool {
+    let web3::Error::Rpc(error) = error else {
+        return false;
+    };
+    let message = error.message.to_lowercase();
+    error.code == jsonrpc_core::ErrorCode::ServerError(-32005)
+        || ["range", "too many", "more than", "too large", "limit exceeded", "exceed maximum"]
+            .iter()
+            .any(|pattern| message.contains(pattern))
+}
+
+pub fn web3_u256_to_f64(value: web3::types::U256) -> f64 {
+    value.0.iter().rev().fold(0.0, |acc, limb| acc * 2.0f64.powi(64) + *limb as f64)
 }
 
 pub fn web3_u256_to_i128(value: web3::types::U256) -> i128 {
@@ -26,3 +55,25 @@ pub fn web3_u256_to_i128(value: web3::types::U256) -> i128 {
     let i256 = ethers_core::types::I256::from_raw(ethers_u256);
     i256.as_i128()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rpc_error(code: i64, message: &str) -> web3::Error {
+        web3::Error::Rpc(jsonrpc_core::Error {
+            code: jsonrpc_core::ErrorCode::ServerError(code),
+            message: message.to_string(),
+            data: None,
+        })
+    }
+
+    #[test]
+    fn is_log_range_error_matches_what_nodes_answer() {
+        assert!(is_log_range_error(&rpc_error(-32005, "query returned more than 10000 results")));
+        assert!(is_log_range_error(&rpc_error(-32000, "block range is too wide")));
+        assert!(is_log_range_error(&rpc_error(-32602, "eth_getLogs is limited to a 10,000 range")));
+        assert!(!is_log_range_error(&rpc_error(-32000, "header not found")));
+        assert!(!is_log_range_error(&web3::Error::Unreachable));
+    }
+}
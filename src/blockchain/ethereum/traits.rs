@@ -1,3 +1,4 @@
+use super::web3_u256_to_f64;
 use web3::types::{Address, H256, U256};
 
 pub trait HexParseTrait {
@@ -33,14 +34,12 @@ impl HexParseTrait for Address {
 }
 
 pub trait FromWeiTrait {
+    #[allow(clippy::wrong_self_convention)]
     fn from_wei(&self, decimals: u64) -> f64;
 }
 
 impl FromWeiTrait for U256 {
     fn from_wei(&self, decimals: u64) -> f64 {
-        let divisor = U256::from(10).pow(U256::from(decimals));
-        let numerator = self.as_u128() as f64;
-        let denominator = divisor.as_u128() as f64;
-        numerator / denominator
+        web3_u256_to_f64(*self) / 10f64.powi(decimals as i32)
     }
 }
@@ -0,0 +1,62 @@
+use super::{Web3Client, Web3Ex};
+use web3::types::{Address, BlockId, Log, U256};
+
+const UNISWAPV2_PAIR_ABI: &str = include_str!("./abi/uniswapv2_pair.json");
+
+/// Swap(address,uint256,uint256,uint256,uint256,address), shared by Uniswap V2 and its forks
+pub const SWAP_TOPIC: &str = "0xd78ad95fa46c994b6551d0da85fc275fe613ce37657fb8d5e3d130840159d822";
+
+pub async fn get_reserves(client: &Web3Client, pool: Address, block: Option<BlockId>) -> web3::contract::Result<(U256, U256)> {
+    let result: (U256, U256, u64) = client.query_smart_contract(pool, UNISWAPV2_PAIR_ABI, "getReserves", (), block).await?;
+    Ok((result.0, result.1))
+}
+
+pub async fn get_tokens(client: &Web3Client, pool: Address) -> web3::contract::Result<(Address, Address)> {
+    futures::try_join!(
+        client.query_smart_contract(pool, UNISWAPV2_PAIR_ABI, "token0", (), None),
+        client.query_smart_contract(pool, UNISWAPV2_PAIR_ABI, "token1", (), None)
+    )
+}
+
+/// Net token amounts of a V2 swap seen from the pool, positive when flowing in, like the V3 Swap event
+pub fn parse_swap_amount(log: &Log) -> Option<(i128, i128)> {
+    let data = log.data.0.as_slice();
+    let word = |index: usize| data.get(index * 32..(index + 1) * 32).map(|word| u256_to_i128(U256::from_big_endian(word)));
+    let (amount0_in, amount1_in, amount0_out, amount1_out) = (word(0)?, word(1)?, word(2)?, word(3)?);
+
+    Some((amount0_in.saturating_sub(amount0_out), amount1_in.saturating_sub(amount1_out)))
+}
+
+fn u256_to_i128(value: U256) -> i128 {
+    if value > U256::from(i128::MAX as u128) {
+        i128::MAX
+    } else {
+        value.as_u128() as i128
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn swap_log(amounts: &[u64]) -> Log {
+        let data: String = amounts.iter().map(|amount| format!("{:064x}", amount)).collect();
+        serde_json::from_value(serde_json::json!({
+            "address": "0xb4e16d0168e52d35cacd2c6185b44281ec28c9dc",
+            "topics": [SWAP_TOPIC],
+            "data": format!("0x{}", data),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn parse_swap_amount_nets_in_and_out() {
+        assert_eq!(parse_swap_amount(&swap_log(&[500, 0, 0, 1000])), Some((500, -1000)));
+        assert_eq!(parse_swap_amount(&swap_log(&[0, 30, 20, 0])), Some((-20, 30)));
+    }
+
+    #[test]
+    fn parse_swap_amount_skips_short_logs() {
+        assert_eq!(parse_swap_amount(&swap_log(&[500, 0, 0])), None);
+    }
+}
@@ -1,15 +1,387 @@
-use super::Web3Ex;
-use crate::libs::config::get_web3_rpc_client;
-use web3::types::{Address, BlockId, U256};
+use super::{HexParseTrait, Web3Client, Web3Ex};
+use web3::types::{Address, BlockId, Log, U256};
 
 const UNISWAPV3_PAIR_ABI: &str = include_str!("./abi/uniswapv3_pair.json");
 
-pub async fn calc_pool_price(pool: Address, block: Option<BlockId>) -> web3::contract::Result<f64> {
-    let client = get_web3_rpc_client();
-    let result: (U256, i32, u16, u16, u16, u8, bool) = client.query_smart_contract(pool, UNISWAPV3_PAIR_ABI, "slot0", (), block).await?;
-    let sqrt_price_x96 = result.0.as_u128() as f64;
-    let q96 = 2.0f64.powi(96);
-    let sqrt_price = sqrt_price_x96 / q96;
-    let price = 1.0 / (sqrt_price * sqrt_price);
-    Ok(price)
+const PANCAKEV3_PROTOCOL_FEE_DENOMINATOR: f64 = 10000.0;
+
+pub const MINT_TOPIC: &str = "0x7a53080ba414158be7ec69b987b5fb7d07dee101fe85488f0853ae16239d0bde";
+pub const BURN_TOPIC: &str = "0x0c396cd989a39f4459b5fa1aed6a9a8dcdbc45908acfd67e028cd568da98982c";
+pub const COLLECT_TOPIC: &str = "0x70935338e69775456a85ddef226c395fb668b63fa0115f5f20610b388e6ca9c0";
+
+pub const POOL_CREATED_TOPIC: &str = "0x783cca1c0412dd0d695e784568c96da2e9c22ff989357a2e8b1d9b2b4e6b7118";
+
+pub const MIN_TICK: i32 = -887272;
+pub const MAX_TICK: i32 = 887272;
+
+/// sqrtPriceX96, tick, observationIndex, observationCardinality, observationCardinalityNext, feeProtocol, unlocked.
+/// PancakeSwap V3 widens feeProtocol to uint32 with 16 bits per token, decoding it as u32 reads both layouts
+pub type Slot0 = (U256, i32, u16, u16, u16, u32, bool);
+
+/// How the feeProtocol of slot0 packs the protocol fee of each token, set per factory in the config
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeeProtocolLayout {
+    /// 1/N of the fees with N in 4 bits per token
+    #[default]
+    Uniswap,
+    /// A share in 1/10000 in 16 bits per token
+    Pancake,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PoolState {
+    pub sqrt_price_x96: U256,
+    pub tick: i32,
+    pub liquidity: u128,
+    pub fee_protocol: u32,
+}
+
+/// Price of one `base` token in `quote` tokens, both given as (token, decimals) and being the two tokens of the pool
+pub async fn calc_pool_price(client: &Web3Client, pool: Address, base: (Address, u64), quote: (Address, u64), block: Option<BlockId>) -> anyhow::Result<f64> {
+    let (slot0, token0): (Slot0, Address) = futures::try_join!(
+        client.query_smart_contract(pool, UNISWAPV3_PAIR_ABI, "slot0", (), block),
+        client.query_smart_contract(pool, UNISWAPV3_PAIR_ABI, "token0", (), block)
+    )?;
+
+    let base_is_token0 = if token0 == base.0 {
+        true
+    } else if token0 == quote.0 {
+        false
+    } else {
+        return Err(anyhow::anyhow!("Pool {:?} does not trade {:?} against {:?}", pool, base.0, quote.0));
+    };
+
+    Ok(calc_token_price(sqrt_price_x96_to_price(slot0.0), base_is_token0, base.1, quote.1))
+}
+
+pub async fn get_tokens(client: &Web3Client, pool: Address) -> web3::contract::Result<(Address, Address)> {
+    futures::try_join!(
+        client.query_smart_contract(pool, UNISWAPV3_PAIR_ABI, "token0", (), None),
+        client.query_smart_contract(pool, UNISWAPV3_PAIR_ABI, "token1", (), None)
+    )
+}
+
+/// Raw token1/token0 price, squaring sqrtPriceX96 in 512 bits so nothing is truncated before the float conversion
+pub fn sqrt_price_x96_to_price(sqrt_price_x96: U256) -> f64 {
+    let price_x192 = sqrt_price_x96.full_mul(sqrt_price_x96);
+    let price_x192 = price_x192.0.iter().rev().fold(0.0, |acc, limb| acc * 2.0f64.powi(64) + *limb as f64);
+    price_x192 / 2.0f64.powi(192)
+}
+
+/// Convert a raw token1/token0 price into the decimal-adjusted price of the base token in the quote token
+pub fn calc_token_price(raw_price: f64, base_is_token0: bool, base_decimals: u64, quote_decimals: u64) -> f64 {
+    let price = if base_is_token0 { raw_price } else { 1.0 / raw_price };
+    price * 10f64.powi(base_decimals as i32 - quote_decimals as i32)
+}
+
+pub async fn get_pool_state(client: &Web3Client, pool: Address, block: Option<BlockId>) -> web3::contract::Result<PoolState> {
+    let (slot0, liquidity): (Slot0, u128) = futures::try_join!(
+        client.query_smart_contract(pool, UNISWAPV3_PAIR_ABI, "slot0", (), block),
+        client.query_smart_contract(pool, UNISWAPV3_PAIR_ABI, "liquidity", (), block)
+    )?;
+    Ok(PoolState {
+        sqrt_price_x96: slot0.0,
+        tick: slot0.1,
+        liquidity,
+        fee_protocol: slot0.5,
+    })
+}
+
+/// feeGrowthGlobal0X128 and feeGrowthGlobal1X128, the LP fees earned per unit of liquidity since the pool was created
+pub async fn get_fee_growth(client: &Web3Client, pool: Address, block: Option<BlockId>) -> web3::contract::Result<(U256, U256)> {
+    futures::try_join!(
+        client.query_smart_contract(pool, UNISWAPV3_PAIR_ABI, "feeGrowthGlobal0X128", (), block),
+        client.query_smart_contract(pool, UNISWAPV3_PAIR_ABI, "feeGrowthGlobal1X128", (), block)
+    )
+}
+
+/// Share of the swap fees taken by the protocol fee switch, averaged over both swap directions
+pub fn calc_protocol_fee_share(layout: FeeProtocolLayout, fee_protocol: u32) -> f64 {
+    let (share0, share1) = match layout {
+        FeeProtocolLayout::Pancake => (
+            (fee_protocol % 65536) as f64 / PANCAKEV3_PROTOCOL_FEE_DENOMINATOR,
+            (fee_protocol >> 16) as f64 / PANCAKEV3_PROTOCOL_FEE_DENOMINATOR,
+        ),
+        FeeProtocolLayout::Uniswap => {
+            let denominator_share = |denominator: u32| if denominator == 0 { 0.0 } else { 1.0 / denominator as f64 };
+            (denominator_share(fee_protocol % 16), denominator_share((fee_protocol >> 4) % 16))
+        }
+    };
+    (share0 + share1) / 2.0
+}
+
+pub fn tick_to_sqrt_price(tick: i32) -> f64 {
+    1.0001f64.powf(tick as f64 / 2.0)
+}
+
+/// Raw token amounts held by `liquidity` between two ticks at the current sqrt price
+pub fn calc_range_amounts(liquidity: f64, sqrt_price: f64, tick_lower: i32, tick_upper: i32) -> (f64, f64) {
+    let sqrt_price_lower = tick_to_sqrt_price(tick_lower);
+    let sqrt_price_upper = tick_to_sqrt_price(tick_upper);
+    let sqrt_price = sqrt_price.clamp(sqrt_price_lower, sqrt_price_upper);
+
+    let amount0 = liquidity * (sqrt_price_upper - sqrt_price) / (sqrt_price * sqrt_price_upper);
+    let amount1 = liquidity * (sqrt_price - sqrt_price_lower);
+    (amount0, amount1)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LiquidityEventKind {
+    Mint,
+    Burn,
+    Collect,
+}
+
+impl LiquidityEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LiquidityEventKind::Mint => "mint",
+            LiquidityEventKind::Burn => "burn",
+            LiquidityEventKind::Collect => "collect",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LiquidityEvent {
+    pub kind: LiquidityEventKind,
+    pub owner: Address,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub liquidity: u128,
+    pub amount0: U256,
+    pub amount1: U256,
+}
+
+/// Decode a Mint, Burn or Collect log, the owner and the tick range are indexed in all three
+pub fn parse_liquidity_log(log: &Log, topic: &str) -> Option<LiquidityEvent> {
+    let kind = match topic {
+        MINT_TOPIC => LiquidityEventKind::Mint,
+        BURN_TOPIC => LiquidityEventKind::Burn,
+        COLLECT_TOPIC => LiquidityEventKind::Collect,
+        _ => return None,
+    };
+    if log.topics.len() < 4 {
+        return None;
+    }
+
+    let data = log.data.0.as_slice();
+    let word = |index: usize| data.get(index * 32..(index + 1) * 32).map(U256::from_big_endian);
+    // Mint has the sender before the amounts, Collect the recipient instead of the liquidity
+    let (liquidity, amount0, amount1) = match kind {
+        LiquidityEventKind::Mint => (word(1)?.low_u128(), word(2)?, word(3)?),
+        LiquidityEventKind::Burn => (word(0)?.low_u128(), word(1)?, word(2)?),
+        LiquidityEventKind::Collect => (0, word(1)?, word(2)?),
+    };
+    // int24 is sign extended to the whole topic, its last 4 bytes are the i32
+    let topic_tick = |index: usize| i32::from_be_bytes(log.topics[index].0[28..32].try_into().unwrap_or_default());
+    Some(LiquidityEvent {
+        kind,
+        owner: Address::from(log.topics[1]),
+        tick_lower: topic_tick(2),
+        tick_upper: topic_tick(3),
+        liquidity,
+        amount0,
+        amount1,
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PoolCreated {
+    pub token0: Address,
+    pub token1: Address,
+    pub fee: u64,
+    pub tick_spacing: i32,
+    pub pool: Address,
+}
+
+/// Decode a factory PoolCreated log, the tokens and the fee are indexed
+pub fn parse_pool_created_log(log: &Log) -> Option<PoolCreated> {
+    if log.topics.len() < 4 || log.topics[0].to_hex_string() != POOL_CREATED_TOPIC {
+        return None;
+    }
+
+    let data = log.data.0.as_slice();
+    let tick_spacing = i32::from_be_bytes(data.get(28..32)?.try_into().ok()?);
+    let pool = Address::from_slice(data.get(44..64)?);
+    Some(PoolCreated {
+        token0: Address::from(log.topics[1]),
+        token1: Address::from(log.topics[2]),
+        fee: U256::from_big_endian(log.topics[3].as_bytes()).low_u64(),
+        tick_spacing,
+        pool,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::tools::assert_close;
+
+    const USDC_WETH_POOL: &str = "0x88e6a0c2ddd26feeb64f039a2c41296fcb3f5640";
+    const POSITION_MANAGER: &str = "0xc36442b4a4522e871399cd717abdd847ab11fe88";
+
+    /// A log as eth_getLogs returns it
+    fn log(address: &str, topics: &[&str], data: &str) -> Log {
+        serde_json::from_value(serde_json::json!({
+            "address": address,
+            "topics": topics,
+            "data": data,
+            "blockNumber": "0x12a05f2",
+            "transactionHash": "0x1111111111111111111111111111111111111111111111111111111111111111",
+            "logIndex": "0x7",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn sqrt_price_x96_to_price_squares_the_q96_value() {
+        let q96 = U256::one() << 96;
+        assert_close(sqrt_price_x96_to_price(q96), 1.0, 1e-9);
+        assert_close(sqrt_price_x96_to_price(q96 * 2), 4.0, 1e-9);
+        assert_close(sqrt_price_x96_to_price(q96 / 4), 0.0625, 1e-9);
+    }
+
+    #[test]
+    fn sqrt_price_x96_to_price_keeps_the_extreme_prices() {
+        // MAX_SQRT_RATIO of the pool, its square overflows U256
+        let max_sqrt_price = U256::from_dec_str("1461446703485210103287273052203988822378723970342").unwrap();
+        assert_close(sqrt_price_x96_to_price(max_sqrt_price), 1.0001f64.powi(MAX_TICK), 1e-9);
+        // MIN_SQRT_RATIO
+        assert_close(sqrt_price_x96_to_price(U256::from(4295128739u64)), 1.0001f64.powi(MIN_TICK), 1e-9);
+    }
+
+    #[test]
+    fn calc_token_price_orients_by_token_order_and_decimals() {
+        // USDC (6 decimals) / WETH (18 decimals) at 2000 USDC per WETH
+        let raw_price = sqrt_price_x96_to_price(U256::from_dec_str("1771595571142957102961017161607260").unwrap());
+        assert_close(raw_price, 5e8, 1e-9);
+        assert_close(calc_token_price(raw_price, false, 18, 6), 2000.0, 1e-9);
+        assert_close(calc_token_price(raw_price, true, 6, 18), 0.0005, 1e-9);
+        assert_close(calc_token_price(2.5, true, 18, 18), 2.5, 1e-9);
+        assert_close(calc_token_price(2.5, false, 18, 18), 0.4, 1e-9);
+    }
+
+    #[test]
+    fn calc_protocol_fee_share_uniswap_layout() {
+        // 4 bits per token, the protocol takes 1/denominator of the fees
+        assert_eq!(calc_protocol_fee_share(FeeProtocolLayout::Uniswap, 0), 0.0);
+        assert_close(calc_protocol_fee_share(FeeProtocolLayout::Uniswap, 4 | (4 << 4)), 0.25, 1e-9);
+        assert_close(calc_protocol_fee_share(FeeProtocolLayout::Uniswap, 4 | (5 << 4)), 0.225, 1e-9);
+        assert_close(calc_protocol_fee_share(FeeProtocolLayout::Uniswap, 4), 0.125, 1e-9);
+    }
+
+    #[test]
+    fn calc_protocol_fee_share_pancake_layout() {
+        // 16 bits per token in hundredths of a percent
+        assert_eq!(calc_protocol_fee_share(FeeProtocolLayout::Pancake, 0), 0.0);
+        assert_close(calc_protocol_fee_share(FeeProtocolLayout::Pancake, 3200 | (3200 << 16)), 0.32, 1e-9);
+        assert_close(calc_protocol_fee_share(FeeProtocolLayout::Pancake, 3400 | (3000 << 16)), 0.32, 1e-9);
+        assert_close(calc_protocol_fee_share(FeeProtocolLayout::Pancake, 10000), 0.5, 1e-9);
+    }
+
+    #[test]
+    fn parse_liquidity_log_mint() {
+        let log = log(
+            USDC_WETH_POOL,
+            &[
+                MINT_TOPIC,
+                "0x000000000000000000000000c36442b4a4522e871399cd717abdd847ab11fe88",
+                "0x000000000000000000000000000000000000000000000000000000000002f5d0",
+                "0x0000000000000000000000000000000000000000000000000000000000030958",
+            ],
+            "0x000000000000000000000000c36442b4a4522e871399cd717abdd847ab11fe88000000000000000000000000000000000000000000000000112210f47de98115000000000000000000000000000000000000000000000000000000012a05f2000000000000000000000000000000000000000000000000001bc16d674ec80000",
+        );
+        let event = parse_liquidity_log(&log, MINT_TOPIC).unwrap();
+        assert_eq!(event.kind, LiquidityEventKind::Mint);
+        assert_eq!(event.owner.to_hex_string(), POSITION_MANAGER);
+        assert_eq!((event.tick_lower, event.tick_upper), (194000, 199000));
+        assert_eq!(event.liquidity, 1234567890123456789);
+        assert_eq!(event.amount0, U256::from(5000000000u64));
+        assert_eq!(event.amount1, U256::exp10(18) * 2);
+    }
+
+    #[test]
+    fn parse_liquidity_log_burn_with_negative_tick() {
+        let log = log(
+            USDC_WETH_POOL,
+            &[
+                BURN_TOPIC,
+                "0x000000000000000000000000c36442b4a4522e871399cd717abdd847ab11fe88",
+                "0xfffffffffffffffffffffffffffffffffffffffffffffffffffffffffff2761a",
+                "0x00000000000000000000000000000000000000000000000000000000000d89e6",
+            ],
+            "0x000000000000000000000000000000000000000000000000112210f47de9811500000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000de0b6b3a7640000",
+        );
+        let event = parse_liquidity_log(&log, BURN_TOPIC).unwrap();
+        assert_eq!(event.kind, LiquidityEventKind::Burn);
+        assert_eq!((event.tick_lower, event.tick_upper), (-887270, 887270));
+        assert_eq!(event.liquidity, 1234567890123456789);
+        assert_eq!(event.amount0, U256::zero());
+        assert_eq!(event.amount1, U256::exp10(18));
+    }
+
+    #[test]
+    fn parse_liquidity_log_collect() {
+        let log = log(
+            USDC_WETH_POOL,
+            &[
+                COLLECT_TOPIC,
+                "0x000000000000000000000000c36442b4a4522e871399cd717abdd847ab11fe88",
+                "0x000000000000000000000000000000000000000000000000000000000002f5d0",
+                "0x0000000000000000000000000000000000000000000000000000000000030958",
+            ],
+            "0x000000000000000000000000c36442b4a4522e871399cd717abdd847ab11fe88000000000000000000000000000000000000000000000000000000012a05f2000000000000000000000000000000000000000000000000001bc16d674ec80000",
+        );
+        let event = parse_liquidity_log(&log, COLLECT_TOPIC).unwrap();
+        assert_eq!(event.kind, LiquidityEventKind::Collect);
+        assert_eq!(event.liquidity, 0);
+        assert_eq!(event.amount0, U256::from(5000000000u64));
+        assert_eq!(event.amount1, U256::exp10(18) * 2);
+    }
+
+    #[test]
+    fn parse_liquidity_log_skips_other_and_short_logs() {
+        let topics = [
+            MINT_TOPIC,
+            "0x000000000000000000000000c36442b4a4522e871399cd717abdd847ab11fe88",
+            "0x000000000000000000000000000000000000000000000000000000000002f5d0",
+        ];
+        assert!(parse_liquidity_log(&log(USDC_WETH_POOL, &topics, "0x"), MINT_TOPIC).is_none());
+        assert!(parse_liquidity_log(&log(USDC_WETH_POOL, &topics, "0x"), POOL_CREATED_TOPIC).is_none());
+    }
+
+    #[test]
+    fn parse_pool_created_log_of_the_usdc_weth_pool() {
+        let log = log(
+            "0x1f98431c8ad98523631ae4a59f267346ea31f984",
+            &[
+                POOL_CREATED_TOPIC,
+                "0x000000000000000000000000a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+                "0x000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+                "0x00000000000000000000000000000000000000000000000000000000000001f4",
+            ],
+            "0x000000000000000000000000000000000000000000000000000000000000000a00000000000000000000000088e6a0c2ddd26feeb64f039a2c41296fcb3f5640",
+        );
+        let created = parse_pool_created_log(&log).unwrap();
+        assert_eq!(created.token0.to_hex_string(), "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48");
+        assert_eq!(created.token1.to_hex_string(), "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2");
+        assert_eq!(created.fee, 500);
+        assert_eq!(created.tick_spacing, 10);
+        assert_eq!(created.pool.to_hex_string(), USDC_WETH_POOL);
+
+        let mint = Log {
+            topics: [vec![MINT_TOPIC.parse().unwrap()], log.topics[1..].to_vec()].concat(),
+            ..log.clone()
+        };
+        assert!(parse_pool_created_log(&mint).is_none());
+        assert!(
+            parse_pool_created_log(&Log {
+                data: vec![0; 32].into(),
+                ..log
+            })
+            .is_none()
+        );
+    }
 }
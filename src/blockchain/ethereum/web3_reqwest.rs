@@ -53,6 +53,12 @@ impl Transport for ReqwestTransport {
                 .map_err(|e| web3::Error::Transport(web3::error::TransportError::Message(e.to_string())))?
                 .map_err(|e| web3::Error::Transport(web3::error::TransportError::Message(e.to_string())))?;
 
+            // An error answered by the node, like a log query over too many blocks, is not a transport error
+            if let Some(error) = json.get("error").filter(|error| !error.is_null()) {
+                let error = serde_json::from_value(error.clone()).map_err(|e| web3::Error::Decoder(e.to_string()))?;
+                return Err(web3::Error::Rpc(error));
+            }
+
             Ok(json["result"].clone())
         })
     }
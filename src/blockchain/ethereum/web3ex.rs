@@ -31,7 +31,7 @@ pub trait Web3Ex<T: Transport + Send + Sync> {
     async fn get_chain_id(&self) -> u64;
     async fn get_blocknumber_wait(&self) -> u64;
     async fn get_block_receiepts(&self, blocknumber: BlockId) -> web3::Result<Vec<TransactionReceipt>>;
-    async fn get_event_logs(&self, contracts: &Vec<String>, blocknumber: u64) -> web3::Result<Vec<Log>>;
+    async fn get_event_logs(&self, contracts: &[String], blocknumber: u64) -> web3::Result<Vec<Log>>;
     async fn get_erc20_balance(&self, contract_address: Address, address: Address) -> web3::contract::Result<U256>;
     async fn get_erc20_info(&self, token_contract: Address) -> web3::contract::Result<ERC20TokenInfo>;
     async fn query_smart_contract<R, P>(
@@ -76,7 +76,7 @@ where
         Ok(receipts)
     }
 
-    async fn get_event_logs(&self, contracts: &Vec<String>, blocknumber: u64) -> web3::Result<Vec<Log>> {
+    async fn get_event_logs(&self, contracts: &[String], blocknumber: u64) -> web3::Result<Vec<Log>> {
         let filter = FilterBuilder::default()
             .address(contracts.iter().map(|x| x.parse().unwrap()).collect())
             .from_block(blocknumber.into())
@@ -0,0 +1,107 @@
+use crate::{blockchain::ethereum::web3_u256_to_f64, libs::config::VOLUME_WINDOWS};
+use std::collections::VecDeque;
+use web3::types::U256;
+
+#[derive(Debug, Clone, Copy)]
+pub struct FeeGrowthSample {
+    pub timestamp: u64,
+    pub fee_growth0_x128: U256,
+    pub fee_growth1_x128: U256,
+}
+
+/// Raw token fees earned per unit of in-range liquidity between two samples
+#[derive(Debug, Clone, Copy)]
+pub struct RealizedFeeGrowth {
+    pub hours: f64,
+    pub fee_growth0: f64,
+    pub fee_growth1: f64,
+}
+
+/// feeGrowthGlobal samples of one V3 pool, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct FeeGrowthHistory {
+    pub samples: VecDeque<FeeGrowthSample>,
+}
+
+impl FeeGrowthHistory {
+    pub fn push(&mut self, sample: FeeGrowthSample) {
+        self.samples.push_back(sample);
+
+        // Keep enough history for the longest volume window
+        let retention = VOLUME_WINDOWS.iter().map(|window| window.minutes * 60).max().unwrap_or(0);
+        let min_timestamp = sample.timestamp.saturating_sub(retention);
+        while self.samples.front().is_some_and(|sample| sample.timestamp < min_timestamp) {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Fee growth from the oldest sample within `minutes` of the latest one, None until there are two samples
+    pub fn get_realized(&self, minutes: u64) -> Option<RealizedFeeGrowth> {
+        let latest = self.samples.back()?;
+        let min_timestamp = latest.timestamp.saturating_sub(minutes * 60);
+        let oldest = self.samples.iter().find(|sample| sample.timestamp >= min_timestamp)?;
+        if oldest.timestamp >= latest.timestamp {
+            return None;
+        }
+
+        // The accumulators are allowed to overflow, so the difference wraps
+        let q128 = 2.0f64.powi(128);
+        Some(RealizedFeeGrowth {
+            hours: (latest.timestamp - oldest.timestamp) as f64 / 3600.0,
+            fee_growth0: web3_u256_to_f64(latest.fee_growth0_x128.overflowing_sub(oldest.fee_growth0_x128).0) / q128,
+            fee_growth1: web3_u256_to_f64(latest.fee_growth1_x128.overflowing_sub(oldest.fee_growth1_x128).0) / q128,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(timestamp: u64, fee_growth0_x128: U256, fee_growth1_x128: U256) -> FeeGrowthSample {
+        FeeGrowthSample {
+            timestamp,
+            fee_growth0_x128,
+            fee_growth1_x128,
+        }
+    }
+
+    #[test]
+    fn get_realized_needs_two_samples() {
+        let mut history = FeeGrowthHistory::default();
+        assert!(history.get_realized(60).is_none());
+        history.push(sample(1_000_000, U256::zero(), U256::zero()));
+        assert!(history.get_realized(60).is_none());
+    }
+
+    #[test]
+    fn get_realized_takes_the_oldest_sample_in_the_window() {
+        let q128 = U256::one() << 128;
+        let mut history = FeeGrowthHistory::default();
+        history.push(sample(1_000_000, U256::zero(), U256::zero()));
+        history.push(sample(1_000_000 + 1800, q128, q128 * 2));
+        history.push(sample(1_000_000 + 3600, q128 * 3, q128 * 4));
+
+        let realized = history.get_realized(60).unwrap();
+        assert_eq!(realized.hours, 1.0);
+        assert_eq!(realized.fee_growth0, 3.0);
+        assert_eq!(realized.fee_growth1, 4.0);
+
+        let realized = history.get_realized(30).unwrap();
+        assert_eq!(realized.hours, 0.5);
+        assert_eq!(realized.fee_growth0, 2.0);
+        assert_eq!(realized.fee_growth1, 2.0);
+    }
+
+    #[test]
+    fn get_realized_wraps_around_the_accumulator_overflow() {
+        let q128 = U256::one() << 128;
+        let mut history = FeeGrowthHistory::default();
+        history.push(sample(1_000_000, U256::MAX - q128 + 1, U256::MAX));
+        history.push(sample(1_000_000 + 3600, q128, q128 * 5 - 1));
+
+        let realized = history.get_realized(60).unwrap();
+        assert_eq!(realized.fee_growth0, 2.0);
+        assert_eq!(realized.fee_growth1, 5.0);
+    }
+}
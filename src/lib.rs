@@ -1,7 +1,13 @@
 pub mod api;
 pub mod blockchain;
+pub mod fee_growth;
 pub mod libs;
 pub mod models;
+pub mod position_simulator;
+pub mod price_oracle;
+pub mod swap_filter;
+pub mod volatility;
+pub mod volume_window;
 pub mod yield_scaner;
 
 #[macro_use]
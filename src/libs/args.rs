@@ -1,3 +1,4 @@
+use crate::volume_window::VolumeWindow;
 use clap::Parser;
 
 #[derive(Parser, Debug)]
@@ -22,10 +23,77 @@ pub struct Args {
     /// The database file path
     #[arg(long, env, default_value = "db.sqlite")]
     pub db_path: String,
+
+    /// Backfill from this block number instead of the current head
+    #[arg(long, env)]
+    pub from_block: Option<u64>,
+
+    /// Stop scanning after this block number
+    #[arg(long, env)]
+    pub to_block: Option<u64>,
+
+    /// The chain the --from-block/--to-block range applies to, defaults to the first chain in the config
+    #[arg(long, env)]
+    pub chain_id: Option<u64>,
+
+    /// The number of blocks fetched concurrently while catching up
+    #[arg(long, env, default_value = "8")]
+    pub scan_concurrency: usize,
+
+    /// The max blocks replayed when resuming from the scan cursor, 0 for no limit
+    #[arg(long, env, default_value = "1200")]
+    pub max_catchup_blocks: u64,
+
+    /// The +/- tick range around the current tick used for the active liquidity depth of V3 pools
+    #[arg(long, env, default_value = "100")]
+    pub active_tick_range: i32,
+
+    /// The max deviation of a stable token from $1 before it is reported as depegged
+    #[arg(long, env, default_value = "0.02")]
+    pub stable_peg_band: f64,
+
+    /// Store every counted swap in the swaps table
+    #[arg(long, env)]
+    pub persist_swaps: bool,
+
+    /// The days stored swaps are kept, 0 to keep them forever
+    #[arg(long, env, default_value = "7")]
+    pub swap_retention_days: u64,
+
+    /// The days stored liquidity events are kept, 0 to keep them forever
+    #[arg(long, env, default_value = "7")]
+    pub liquidity_event_retention_days: u64,
+
+    /// The rolling volume windows, the first one is the default ranking window
+    #[arg(long, env, value_delimiter = ',', default_value = "10m,1h,24h,7d")]
+    pub volume_windows: Vec<VolumeWindow>,
+
+    /// How often fees are compounded per year for APY, 0 for continuous compounding
+    #[arg(long, env, default_value = "365")]
+    pub apy_compounds_per_year: u64,
+
+    /// Seconds between feeGrowthGlobal samples of the V3 pools with volume, 0 to disable
+    #[arg(long, env, default_value = "300")]
+    pub fee_growth_sample_interval: u64,
+
+    /// Flag pools where a single tx sender makes more than this share of the volume
+    #[arg(long, env, default_value = "0.5")]
+    pub max_actor_share: f64,
+
+    /// Sweep the PoolCreated history of the configured V3 factories at startup
+    #[arg(long, env)]
+    pub factory_sweep: bool,
+
+    /// The block the factory sweep starts from
+    #[arg(long, env, default_value = "0")]
+    pub factory_sweep_from_block: u64,
+
+    /// Blocks per eth_getLogs call of the factory sweep
+    #[arg(long, env, default_value = "5000")]
+    pub factory_sweep_batch: u64,
 }
 
 pub fn parse() -> Args {
     dotenv::dotenv().ok();
-    let args = Args::parse();
-    args
+    Args::parse()
 }
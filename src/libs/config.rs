@@ -1,43 +1,86 @@
 use super::{Tools, args::Args};
-use crate::blockchain::ethereum::{Web3Client, init_web3_http};
+use crate::{blockchain::ethereum::uniswapv3::FeeProtocolLayout, volume_window::VolumeWindow};
 use clap::Parser;
 use once_cell::sync::Lazy;
 use serde::Deserialize;
-use std::{collections::HashMap, sync::atomic::AtomicUsize};
+use std::collections::HashMap;
 
 lazy_static! {
-    pub static ref ARGS: Args = Args::parse();
+    pub static ref ARGS: Args = parse_args();
     pub static ref HTTP_BIND: String = ARGS.http_bind.clone();
     pub static ref HTTP_PORT: u16 = ARGS.http_port;
     pub static ref OPEN_FILES_LIMIT: u64 = ARGS.open_files_limit;
     pub static ref RPC_ENDPOINT: String = ARGS.rpc_endpoint.clone();
     pub static ref DB_PATH: String = ARGS.db_path.clone();
+    pub static ref FROM_BLOCK: Option<u64> = ARGS.from_block;
+    pub static ref TO_BLOCK: Option<u64> = ARGS.to_block;
+    pub static ref BACKFILL_CHAIN_ID: Option<u64> = ARGS.chain_id;
+    pub static ref SCAN_CONCURRENCY: usize = ARGS.scan_concurrency;
+    pub static ref MAX_CATCHUP_BLOCKS: u64 = ARGS.max_catchup_blocks;
+    pub static ref ACTIVE_TICK_RANGE: i32 = ARGS.active_tick_range;
+    pub static ref STABLE_PEG_BAND: f64 = ARGS.stable_peg_band;
+    pub static ref PERSIST_SWAPS: bool = ARGS.persist_swaps;
+    pub static ref SWAP_RETENTION_DAYS: u64 = ARGS.swap_retention_days;
+    pub static ref LIQUIDITY_EVENT_RETENTION_DAYS: u64 = ARGS.liquidity_event_retention_days;
+    pub static ref VOLUME_WINDOWS: Vec<VolumeWindow> = ARGS.volume_windows.clone();
+    pub static ref APY_COMPOUNDS_PER_YEAR: u64 = ARGS.apy_compounds_per_year;
+    pub static ref FEE_GROWTH_SAMPLE_INTERVAL: u64 = ARGS.fee_growth_sample_interval;
+    pub static ref MAX_ACTOR_SHARE: f64 = ARGS.max_actor_share;
+    pub static ref FACTORY_SWEEP: bool = ARGS.factory_sweep;
+    pub static ref FACTORY_SWEEP_FROM_BLOCK: u64 = ARGS.factory_sweep_from_block;
+    pub static ref FACTORY_SWEEP_BATCH: u64 = ARGS.factory_sweep_batch;
+}
+
+#[cfg(not(test))]
+fn parse_args() -> Args {
+    Args::parse()
+}
+
+/// Tests run on the defaults, the command line belongs to the test harness
+#[cfg(test)]
+fn parse_args() -> Args {
+    Args::parse_from([env!("CARGO_PKG_NAME"), "--rpc-endpoint", "http://127.0.0.1:8545"])
 }
 
 #[derive(Debug, Clone, Deserialize)]
-pub struct JsonConfig {
+pub struct FactoryConfig {
+    pub protocol: String,
+    #[serde(default)]
+    pub fee_protocol_layout: FeeProtocolLayout,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct V2FactoryConfig {
+    pub protocol: String,
+    pub fee: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChainConfig {
+    pub chain_id: u64,
+    pub name: String,
     pub wrap_token_pool: String,
     pub wrap_token: String,
     pub stable_tokens: HashMap<String, String>,
+    /// The stable taken as exactly $1 when pricing the other stables
+    #[serde(default)]
+    pub peg_reference: Option<String>,
     pub swap_topics: HashMap<String, String>,
-    pub factories: HashMap<String, String>,
+    pub factories: HashMap<String, FactoryConfig>,
+    #[serde(default)]
+    pub v2_factories: HashMap<String, V2FactoryConfig>,
+    #[serde(default)]
+    pub protocol_fee_shares: HashMap<String, f64>,
     pub rpc_endpoints: Vec<String>,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct JsonConfig {
+    pub chains: Vec<ChainConfig>,
+}
+
 pub static JSON_CONFIG: Lazy<JsonConfig> = Lazy::new(|| {
     let json_str = Tools::read_file_text("config.jsonc").unwrap();
     let config = jsonc_parser::parse_to_serde_value(&json_str, &Default::default()).unwrap().unwrap();
     serde_json::from_value(config).unwrap()
 });
-
-pub static CHAIN_GATEWAY_INDEX: AtomicUsize = AtomicUsize::new(0);
-
-pub fn get_rpc_url() -> String {
-    let client_index = CHAIN_GATEWAY_INDEX.load(std::sync::atomic::Ordering::Relaxed);
-    CHAIN_GATEWAY_INDEX.store((client_index + 1) % JSON_CONFIG.rpc_endpoints.len(), std::sync::atomic::Ordering::Relaxed);
-    JSON_CONFIG.rpc_endpoints[client_index].clone()
-}
-
-pub fn get_web3_rpc_client() -> Web3Client {
-    init_web3_http(get_rpc_url().as_str())
-}
@@ -1,10 +1,19 @@
-use super::{Tools, config::DB_PATH};
-use sqlx::{Pool, Sqlite, SqlitePool, sqlite::SqliteConnectOptions};
+use super::{
+    Tools,
+    config::{DB_PATH, JSON_CONFIG},
+};
+use sqlx::{Pool, Row, Sqlite, SqliteConnection, SqlitePool, sqlite::SqliteConnectOptions};
 use std::sync::Arc;
 use tokio::sync::OnceCell;
 
 pub static DB_SQLITE: OnceCell<Arc<Pool<Sqlite>>> = OnceCell::const_new();
 
+/// Tables that became chain scoped, with the columns carried over from the single chain layout
+const CHAIN_SCOPED_TABLES: &[(&str, &str)] = &[(
+    "pools",
+    "protocol, factory, pool, token0, token1, fee, token0_liquidity, token1_liquidity, timestamp",
+)];
+
 pub fn get_sqlite_pool() -> Arc<Pool<Sqlite>> {
     DB_SQLITE.get().unwrap().clone()
 }
@@ -14,7 +23,49 @@ pub async fn sqlite_init() -> anyhow::Result<()> {
     let pool = SqlitePool::connect_with(options).await?;
     DB_SQLITE.set(Arc::new(pool))?;
 
+    // Renaming the legacy tables, recreating them and copying their rows is one transaction,
+    // a failure in between leaves the old layout as it was for the next start
     let init = Tools::read_file_text("./init.sql")?;
-    sqlx::query(&init).execute(get_sqlite_pool().as_ref()).await?;
+    let mut tx = get_sqlite_pool().begin().await?;
+    let legacy_tables = sqlite_rename_legacy_tables(&mut tx).await?;
+    sqlx::query(&init).execute(&mut *tx).await?;
+    sqlite_migrate_legacy_tables(&mut tx, legacy_tables).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Move tables created before multi-chain support out of the way, so init.sql can create the chain scoped ones
+async fn sqlite_rename_legacy_tables(conn: &mut SqliteConnection) -> anyhow::Result<Vec<(&'static str, &'static str)>> {
+    let mut legacy_tables = Vec::new();
+    for (table, columns) in CHAIN_SCOPED_TABLES {
+        let table_columns = sqlx::query("SELECT name FROM pragma_table_info(?)").bind(table).fetch_all(&mut *conn).await?;
+        if table_columns.is_empty() || table_columns.iter().any(|row| row.get::<String, _>("name") == "chain_id") {
+            continue;
+        }
+
+        log::info!("Migrate table {} to chain scoped layout", table);
+        sqlx::query(&format!("ALTER TABLE {table} RENAME TO {table}_legacy"))
+            .execute(&mut *conn)
+            .await?;
+        legacy_tables.push((*table, *columns));
+    }
+    Ok(legacy_tables)
+}
+
+/// Legacy rows all belong to the first configured chain, which is what the single chain binary scanned
+async fn sqlite_migrate_legacy_tables(conn: &mut SqliteConnection, legacy_tables: Vec<(&str, &str)>) -> anyhow::Result<()> {
+    let Some(chain) = JSON_CONFIG.chains.first() else {
+        return Ok(());
+    };
+
+    for (table, columns) in legacy_tables {
+        sqlx::query(&format!(
+            "INSERT OR IGNORE INTO {table} (chain_id, {columns}) SELECT ?, {columns} FROM {table}_legacy"
+        ))
+        .bind(chain.chain_id as i64)
+        .execute(&mut *conn)
+        .await?;
+        sqlx::query(&format!("DROP TABLE {table}_legacy")).execute(&mut *conn).await?;
+    }
     Ok(())
 }
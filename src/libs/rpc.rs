@@ -6,7 +6,7 @@ pub type RpcResultJson = RpcResult<serde_json::Value>;
 
 pub async fn json_rpc_drop(url: &str) {
     http_json_rpc(url, false, None).await.unwrap_or_else(|e| {
-        log::warn!("json_rpc_drop error: {} {}", url.to_string(), e);
+        log::warn!("json_rpc_drop error: {} {}", url, e);
         serde_json::Value::Null
     });
 }
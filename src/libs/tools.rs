@@ -144,3 +144,9 @@ impl Tools {
         }
     }
 }
+
+/// Assert that `value` is within `tolerance` of `expected`, relative to `expected`
+#[cfg(test)]
+pub fn assert_close(value: f64, expected: f64, tolerance: f64) {
+    assert!((value - expected).abs() <= expected.abs() * tolerance, "{value} != {expected}");
+}
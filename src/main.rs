@@ -1,9 +1,13 @@
 use dotenv::dotenv;
 use log::info;
-use tokio;
 use v3scan::{
     api,
-    libs::{adjust_open_files, config, db_sqlite::sqlite_init, log::init_log},
+    libs::{
+        adjust_open_files,
+        config::{self, JSON_CONFIG},
+        db_sqlite::sqlite_init,
+        log::init_log,
+    },
     yield_scaner::V3ScanWorker,
 };
 
@@ -25,9 +29,16 @@ async fn main() -> anyhow::Result<()> {
 
     sqlite_init().await?;
 
-    let app = V3ScanWorker::new();
-    app.init().await.unwrap();
-    app.run().await.unwrap();
+    for chain in JSON_CONFIG.chains.iter() {
+        // A chain whose RPC or native price pool is down at startup must not take the other chains with it
+        let app = V3ScanWorker::new(chain.clone());
+        if let Err(e) = app.init().await {
+            log::error!("[{}] Failed to init chain {}, skipped: {}", chain.name, chain.chain_id, e);
+            V3ScanWorker::remove_worker(chain.chain_id);
+            continue;
+        }
+        app.run().await?;
+    }
 
     api::server::run(true).await;
 
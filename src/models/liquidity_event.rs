@@ -0,0 +1,19 @@
+#[derive(Debug, Clone, Default, serde::Serialize, sqlx::FromRow)]
+pub struct LiquidityEventModel {
+    pub blocknumber: u64,
+    pub tx_hash: String,
+    pub log_index: u64,
+    pub pool: String,
+    /// `mint`, `burn` or `collect`
+    pub event: String,
+    pub owner: String,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    /// Liquidity added or removed, 0 for collect
+    pub liquidity: String,
+    /// Raw token amounts, added to the pool by a mint and taken out by a collect
+    pub amount0: String,
+    pub amount1: String,
+    pub usd_value: f64,
+    pub timestamp: u64,
+}
@@ -1 +1,6 @@
+pub mod liquidity_event;
+pub mod pool_created;
 pub mod pool_info;
+pub mod scan_cursor;
+pub mod swap_event;
+pub mod token_info;
@@ -0,0 +1,13 @@
+#[derive(Debug, Clone, Default, serde::Serialize, sqlx::FromRow)]
+pub struct PoolCreatedModel {
+    pub protocol: String,
+    pub factory: String,
+    pub pool: String,
+    pub token0: String,
+    pub token1: String,
+    pub fee: u64,
+    pub tick_spacing: i32,
+    pub blocknumber: u64,
+    pub tx_hash: String,
+    pub timestamp: u64,
+}
@@ -1,6 +1,6 @@
-use crate::{libs::config::JSON_CONFIG, yield_scaner::NATIVE_TOKEN_PRICE};
+use crate::{blockchain::ethereum::uniswapv3, yield_scaner::V3ScanWorker};
 
-#[derive(Debug, Clone, sqlx::FromRow)]
+#[derive(Debug, Clone, Default, serde::Serialize, sqlx::FromRow)]
 pub struct PoolInfoModel {
     pub protocol: String,
     pub factory: String,
@@ -8,27 +8,123 @@ pub struct PoolInfoModel {
     pub token0: String,
     pub token1: String,
     pub fee: u64,
+    /// Whole-token balances, already divided by the token decimals
     pub token0_liquidity: u64,
     pub token1_liquidity: u64,
     pub timestamp: u64,
+    #[sqlx(skip)]
+    pub sqrt_price: f64,
+    #[sqlx(skip)]
+    pub tick: i32,
+    #[sqlx(skip)]
+    pub liquidity: u128,
+    /// Decimal-adjusted price of token0 in token1 from the raw V2 reserves, 0 for V3 pools and pools not refreshed yet
+    #[sqlx(skip)]
+    pub v2_price: f64,
+    /// Protocol fee share read from slot0, None for V2 pools and pools not refreshed yet
+    #[sqlx(skip)]
+    pub protocol_fee_share: Option<f64>,
+    /// Block the balances and V3 state were last read at, the liquidity events up to it are already in them
+    #[sqlx(skip)]
+    pub refreshed_block: u64,
 }
 
 impl PoolInfoModel {
-    pub fn get_liquidity(&self) -> f64 {
-        let (token, liquidity) = if self.token0 == JSON_CONFIG.wrap_token || JSON_CONFIG.stable_tokens.contains_key(&self.token0) {
-            (&self.token0, self.token0_liquidity as f64)
-        } else if self.token1 == JSON_CONFIG.wrap_token || JSON_CONFIG.stable_tokens.contains_key(&self.token1) {
-            (&self.token1, self.token1_liquidity as f64)
+    /// Whether the quote side is token0, and its USD price. The wrapped native or stable side is preferred,
+    /// otherwise the side with the deeper liquidity among the tokens the price oracle can route
+    pub fn get_quote_token(&self, worker: &V3ScanWorker) -> Option<(bool, f64)> {
+        let config = &worker.config;
+        let (is_token0, token) = if self.token0 == config.wrap_token || config.stable_tokens.contains_key(&self.token0) {
+            (true, &self.token0)
+        } else if self.token1 == config.wrap_token || config.stable_tokens.contains_key(&self.token1) {
+            (false, &self.token1)
         } else {
+            return match (worker.oracle.get_token_price(&self.token0), worker.oracle.get_token_price(&self.token1)) {
+                (Some(price0), Some(price1)) if self.token0_liquidity as f64 * price0 >= self.token1_liquidity as f64 * price1 => Some((true, price0)),
+                (_, Some(price1)) => Some((false, price1)),
+                (Some(price0), None) => Some((true, price0)),
+                (None, None) => None,
+            };
+        };
+
+        let price = if *token == config.wrap_token {
+            worker.get_native_token_price()
+        } else {
+            worker.oracle.get_stable_price(token)
+        };
+        Some((is_token0, price))
+    }
+
+    /// USD prices of token0 and token1, the non-quote side derived from the pool price
+    pub fn get_token_prices(&self, worker: &V3ScanWorker) -> Option<(f64, f64)> {
+        let (is_token0, quote_price) = self.get_quote_token(worker)?;
+        let price = self.get_price(worker)?;
+        if is_token0 {
+            Some((quote_price, quote_price / price))
+        } else {
+            Some((price * quote_price, quote_price))
+        }
+    }
+
+    /// Decimal-adjusted price of token0 in token1
+    pub fn get_price(&self, worker: &V3ScanWorker) -> Option<f64> {
+        let price = if self.sqrt_price > 0.0 {
+            uniswapv3::calc_token_price(
+                self.sqrt_price * self.sqrt_price,
+                true,
+                worker.get_token_decimals(&self.token0),
+                worker.get_token_decimals(&self.token1),
+            )
+        } else if worker.config.v2_factories.contains_key(&self.factory) {
+            self.v2_price
+        } else {
+            return None;
+        };
+
+        price.is_normal().then_some(price)
+    }
+
+    /// USD value of both token balances, on the same basis as the active liquidity. The other side is priced from
+    /// the pool, or the price oracle before the pool price is loaded, and taken as worth the quote side otherwise
+    pub fn get_liquidity(&self, worker: &V3ScanWorker) -> f64 {
+        let Some((is_token0, price)) = self.get_quote_token(worker) else {
             return 0.0;
         };
 
-        let liquidity = if *token == JSON_CONFIG.wrap_token {
-            liquidity * *NATIVE_TOKEN_PRICE.read().unwrap()
+        let (liquidity, other_liquidity, other_token) = if is_token0 {
+            (self.token0_liquidity, self.token1_liquidity, &self.token1)
         } else {
-            liquidity
+            (self.token1_liquidity, self.token0_liquidity, &self.token0)
+        };
+        let other_price = self
+            .get_token_prices(worker)
+            .map(|(price0, price1)| if is_token0 { price1 } else { price0 })
+            .or_else(|| worker.oracle.get_token_price(other_token));
+        let liquidity = liquidity as f64 * price;
+        match other_price {
+            Some(other_price) => liquidity + other_liquidity as f64 * other_price,
+            None => liquidity * 2.0,
+        }
+    }
+
+    /// USD value of both tokens of the in-range liquidity within +/- `tick_range` ticks of the current tick,
+    /// assuming the active liquidity stays constant over that range
+    pub fn get_active_liquidity(&self, worker: &V3ScanWorker, tick_range: i32) -> f64 {
+        if self.sqrt_price == 0.0 || self.liquidity == 0 {
+            return self.get_liquidity(worker);
+        }
+
+        let Some((is_token0, price)) = self.get_quote_token(worker) else {
+            return 0.0;
         };
 
-        liquidity / 10f64.powi(18 as i32)
+        let (amount0, amount1) = uniswapv3::calc_range_amounts(self.liquidity as f64, self.sqrt_price, self.tick - tick_range, self.tick + tick_range);
+        let pool_price = self.sqrt_price * self.sqrt_price;
+        let (token, amount) = if is_token0 {
+            (&self.token0, amount0 + amount1 / pool_price)
+        } else {
+            (&self.token1, amount0 * pool_price + amount1)
+        };
+        amount * price / 10f64.powi(worker.get_token_decimals(token) as i32)
     }
 }
@@ -0,0 +1,6 @@
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct ScanCursorModel {
+    pub blocknumber: u64,
+    pub blockhash: String,
+    pub timestamp: u64,
+}
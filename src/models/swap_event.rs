@@ -0,0 +1,22 @@
+#[derive(Debug, Clone, Default, serde::Serialize, sqlx::FromRow)]
+pub struct SwapEventModel {
+    pub blocknumber: u64,
+    pub tx_hash: String,
+    pub log_index: u64,
+    pub pool: String,
+    /// Raw signed amounts seen from the pool, positive when flowing in
+    pub amount0: String,
+    pub amount1: String,
+    /// Pool state after the swap, only emitted by V3 pools
+    pub sqrt_price_x96: Option<String>,
+    pub tick: Option<i32>,
+    pub liquidity: Option<String>,
+    pub usd_value: f64,
+    pub sender: String,
+    pub recipient: String,
+    pub timestamp: u64,
+    /// Sender of the transaction, the actor the swap is attributed to
+    pub tx_from: String,
+    /// `SwapCategory` of the swap
+    pub category: String,
+}
@@ -0,0 +1,9 @@
+#[derive(Debug, Clone, Default, serde::Serialize, sqlx::FromRow)]
+pub struct TokenInfoModel {
+    pub token: String,
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u64,
+    pub total_supply: String,
+    pub timestamp: u64,
+}
@@ -0,0 +1,294 @@
+use crate::{
+    blockchain::ethereum::{
+        HexParseTrait,
+        uniswapv3::{self, MAX_TICK, MIN_TICK},
+    },
+    volume_window::VolumeWindow,
+    yield_scaner::V3ScanWorker,
+};
+use serde::{Deserialize, Serialize};
+use web3::types::Address;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SimulateRequest {
+    pub pool: String,
+    pub deposit_usd: f64,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    /// Price moves in percent, each is simulated up and down
+    #[serde(default = "SimulateRequest::default_price_moves")]
+    pub price_moves: Vec<f64>,
+    /// Volume window the fees are estimated from, one of `--volume-windows`
+    pub window: Option<String>,
+}
+
+impl SimulateRequest {
+    fn default_price_moves() -> Vec<f64> {
+        vec![5.0, 10.0, 25.0, 50.0]
+    }
+}
+
+/// Value of the position after token0 moves by `price_move` percent against token1, token1 keeping its USD price
+#[derive(Debug, Clone, Serialize)]
+pub struct PriceMoveResult {
+    pub price_move: f64,
+    pub price: f64,
+    pub in_range: bool,
+    pub hold_usd: f64,
+    pub position_usd: f64,
+    pub impermanent_loss: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SimulateResult {
+    pub pool: String,
+    pub window: String,
+    pub hours: f64,
+    pub deposit_usd: f64,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    /// Decimal-adjusted prices of token0 in token1
+    pub price: f64,
+    pub price_lower: f64,
+    pub price_upper: f64,
+    pub in_range: bool,
+    /// Whole tokens deposited at the current price
+    pub amount0: f64,
+    pub amount1: f64,
+    pub liquidity: f64,
+    /// Share of the in-range liquidity once the position is added
+    pub liquidity_share: f64,
+    /// LP fees per hour of the position from the window volume, after the protocol fee share
+    pub fees_per_hour: f64,
+    pub aph: f64,
+    pub realized_fees_per_hour: Option<f64>,
+    pub price_moves: Vec<PriceMoveResult>,
+}
+
+/// Estimate a V3 position from the cached pool state, assuming the volume and the other LPs stay as they are.
+/// Returns None if the pool is not tracked.
+pub fn simulate(worker: &V3ScanWorker, request: &SimulateRequest) -> anyhow::Result<Option<SimulateResult>> {
+    let window = match &request.window {
+        Some(name) => VolumeWindow::find(name).ok_or_else(|| anyhow::anyhow!("unknown window: {}", name))?,
+        None => VolumeWindow::get_default(),
+    };
+    if !(request.deposit_usd.is_finite() && request.deposit_usd > 0.0) {
+        anyhow::bail!("invalid deposit: {}", request.deposit_usd);
+    }
+    if request.tick_lower >= request.tick_upper || request.tick_lower < MIN_TICK || request.tick_upper > MAX_TICK {
+        anyhow::bail!("invalid tick range: {} - {}", request.tick_lower, request.tick_upper);
+    }
+
+    let pool = request.pool.parse::<Address>().map_err(|_| anyhow::anyhow!("invalid address"))?;
+    let Some(info) = worker.get_cached_pool_info(&pool.to_hex_string()) else {
+        return Ok(None);
+    };
+    if worker.config.v2_factories.contains_key(&info.factory) || info.sqrt_price == 0.0 {
+        anyhow::bail!("pool {} is not a V3 pool or its state is not loaded yet", info.pool);
+    }
+    let (price0, price1) = info
+        .get_token_prices(worker)
+        .ok_or_else(|| anyhow::anyhow!("no USD price for pool {}", info.pool))?;
+
+    // USD per raw token unit
+    let decimals0 = 10f64.powi(worker.get_token_decimals(&info.token0) as i32);
+    let decimals1 = 10f64.powi(worker.get_token_decimals(&info.token1) as i32);
+    let (raw_price0, raw_price1) = (price0 / decimals0, price1 / decimals1);
+
+    let (unit_amount0, unit_amount1) = uniswapv3::calc_range_amounts(1.0, info.sqrt_price, request.tick_lower, request.tick_upper);
+    let liquidity = request.deposit_usd / (unit_amount0 * raw_price0 + unit_amount1 * raw_price1);
+    let (amount0, amount1) = (unit_amount0 * liquidity, unit_amount1 * liquidity);
+
+    let in_range = (request.tick_lower..request.tick_upper).contains(&info.tick);
+    let liquidity_share = if in_range { liquidity / (info.liquidity as f64 + liquidity) } else { 0.0 };
+
+    let (now_min, since_min) = worker.get_volume_clock();
+    let volume = worker.get_pool_volume(&info.pool).get_volume(window, now_min);
+    let hours = window.get_elapsed_hours(now_min, since_min);
+    let lp_share = 1.0 - worker.get_protocol_fee_share(&info);
+    let fees_per_hour = V3ScanWorker::calc_fee_rate_per_hour(info.fee, volume, 1.0, hours) * lp_share * liquidity_share;
+    let realized_fees_per_hour = worker
+        .calc_realized_fees_per_hour(&info, window.minutes)
+        .map(|(_, fees_per_hour)| fees_per_hour * liquidity_share);
+
+    let decimal_price = |sqrt_price: f64| sqrt_price * sqrt_price * decimals0 / decimals1;
+    let mut moves: Vec<f64> = request
+        .price_moves
+        .iter()
+        .flat_map(|price_move| [-price_move.abs(), price_move.abs()])
+        .collect();
+    moves.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    moves.dedup();
+    let price_moves = moves
+        .into_iter()
+        .filter(|price_move| *price_move > -100.0)
+        .map(|price_move| {
+            let factor = 1.0 + price_move / 100.0;
+            let sqrt_price = info.sqrt_price * factor.sqrt();
+            let (moved_amount0, moved_amount1) = uniswapv3::calc_range_amounts(liquidity, sqrt_price, request.tick_lower, request.tick_upper);
+            let hold_usd = amount0 * raw_price0 * factor + amount1 * raw_price1;
+            let position_usd = moved_amount0 * raw_price0 * factor + moved_amount1 * raw_price1;
+            PriceMoveResult {
+                price_move,
+                price: decimal_price(sqrt_price),
+                in_range: sqrt_price >= uniswapv3::tick_to_sqrt_price(request.tick_lower) && sqrt_price < uniswapv3::tick_to_sqrt_price(request.tick_upper),
+                hold_usd,
+                position_usd,
+                impermanent_loss: position_usd / hold_usd - 1.0,
+            }
+        })
+        .collect();
+
+    Ok(Some(SimulateResult {
+        pool: info.pool.clone(),
+        window: window.name.clone(),
+        hours,
+        deposit_usd: request.deposit_usd,
+        tick_lower: request.tick_lower,
+        tick_upper: request.tick_upper,
+        price: decimal_price(info.sqrt_price),
+        price_lower: decimal_price(uniswapv3::tick_to_sqrt_price(request.tick_lower)),
+        price_upper: decimal_price(uniswapv3::tick_to_sqrt_price(request.tick_upper)),
+        in_range,
+        amount0: amount0 / decimals0,
+        amount1: amount1 / decimals1,
+        liquidity,
+        liquidity_share,
+        fees_per_hour,
+        aph: fees_per_hour / request.deposit_usd,
+        realized_fees_per_hour,
+        price_moves,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        libs::{config::ChainConfig, tools::assert_close},
+        models::pool_info::PoolInfoModel,
+    };
+    use std::collections::HashMap;
+
+    const POOL: &str = "0x000000000000000000000000000000000000dead";
+    const STABLE: &str = "0x55d398326f99059ff775485246999027b3197955";
+
+    /// A pool of a stable against a token at 1 stable, both with 18 decimals, at tick 0
+    fn worker() -> V3ScanWorker {
+        let worker = V3ScanWorker::create(ChainConfig {
+            chain_id: 56,
+            name: "bsc".to_string(),
+            wrap_token_pool: String::new(),
+            wrap_token: String::new(),
+            stable_tokens: HashMap::from([(STABLE.to_string(), "USDT".to_string())]),
+            peg_reference: None,
+            swap_topics: HashMap::new(),
+            factories: HashMap::new(),
+            v2_factories: HashMap::new(),
+            protocol_fee_shares: HashMap::new(),
+            rpc_endpoints: Vec::new(),
+        });
+        worker.insert_pool_info(PoolInfoModel {
+            protocol: "uniswapv3".to_string(),
+            pool: POOL.to_string(),
+            token0: STABLE.to_string(),
+            token1: "0x0000000000000000000000000000000000000001".to_string(),
+            fee: 500,
+            sqrt_price: 1.0,
+            liquidity: 10u128.pow(21),
+            protocol_fee_share: Some(0.0),
+            ..Default::default()
+        });
+        worker
+    }
+
+    fn request(tick_lower: i32, tick_upper: i32) -> SimulateRequest {
+        SimulateRequest {
+            pool: POOL.to_string(),
+            deposit_usd: 1000.0,
+            tick_lower,
+            tick_upper,
+            price_moves: SimulateRequest::default_price_moves(),
+            window: None,
+        }
+    }
+
+    #[test]
+    fn simulate_splits_a_centered_range_evenly() {
+        let worker = worker();
+        let result = simulate(&worker, &request(-1000, 1000)).unwrap().unwrap();
+
+        assert!(result.in_range);
+        assert_close(result.price, 1.0, 1e-6);
+        assert_close(result.amount0, 500.0, 1e-6);
+        assert_close(result.amount1, 500.0, 1e-6);
+        assert_close(result.liquidity_share, result.liquidity / (1e21 + result.liquidity), 1e-6);
+
+        // 1.0001^1000 is about 10.5% up and 9.5% down
+        let moves: Vec<(f64, bool)> = result
+            .price_moves
+            .iter()
+            .map(|price_move| (price_move.price_move, price_move.in_range))
+            .collect();
+        assert_eq!(
+            moves,
+            [
+                (-50.0, false),
+                (-25.0, false),
+                (-10.0, false),
+                (-5.0, true),
+                (5.0, true),
+                (10.0, true),
+                (25.0, false),
+                (50.0, false)
+            ]
+        );
+        for price_move in result.price_moves.iter() {
+            assert!(price_move.impermanent_loss < 0.0, "{:?}", price_move);
+        }
+    }
+
+    #[test]
+    fn simulate_range_above_the_price_is_all_token0() {
+        let worker = worker();
+        let result = simulate(&worker, &request(1000, 2000)).unwrap().unwrap();
+
+        assert!(!result.in_range);
+        assert_close(result.amount0, 1000.0, 1e-6);
+        assert_close(result.amount1, 0.0, 1e-6);
+        assert_eq!(result.liquidity_share, 0.0);
+        assert_eq!(result.fees_per_hour, 0.0);
+    }
+
+    #[test]
+    fn simulate_rejects_bad_requests() {
+        let worker = worker();
+        assert!(simulate(&worker, &request(1000, 1000)).is_err());
+        assert!(simulate(&worker, &request(MIN_TICK - 1, 0)).is_err());
+        assert!(
+            simulate(
+                &worker,
+                &SimulateRequest {
+                    deposit_usd: 0.0,
+                    ..request(-1000, 1000)
+                }
+            )
+            .is_err()
+        );
+        assert!(
+            simulate(
+                &worker,
+                &SimulateRequest {
+                    window: Some("2m".to_string()),
+                    ..request(-1000, 1000)
+                }
+            )
+            .is_err()
+        );
+        let unknown_pool = SimulateRequest {
+            pool: "0x000000000000000000000000000000000000beef".to_string(),
+            ..request(-1000, 1000)
+        };
+        assert!(simulate(&worker, &unknown_pool).unwrap().is_none());
+    }
+}
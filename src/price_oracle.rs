@@ -0,0 +1,197 @@
+use crate::{
+    libs::{config::STABLE_PEG_BAND, global::LoopResult},
+    models::pool_info::PoolInfoModel,
+    yield_scaner::V3ScanWorker,
+};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+const MAX_ROUTE_HOPS: usize = 3;
+const MIN_ROUTE_LIQUIDITY: f64 = 10000.0;
+/// Quotes against other stables needed to price a stable by their median, so one depegged stable is outvoted
+const MIN_MEDIAN_QUOTES: usize = 3;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StablePeg {
+    pub token: String,
+    pub symbol: String,
+    pub price: f64,
+    pub depegged: bool,
+}
+
+/// USD prices of the tokens of one chain
+#[derive(Default)]
+pub struct PriceOracle {
+    token_prices: RwLock<HashMap<String, f64>>,
+    stable_pegs: RwLock<HashMap<String, StablePeg>>,
+}
+
+impl PriceOracle {
+    pub fn get_token_price(&self, token: &str) -> Option<f64> {
+        self.token_prices.read().unwrap().get(token).copied()
+    }
+
+    pub fn get_stable_price(&self, token: &str) -> f64 {
+        self.stable_pegs.read().unwrap().get(token).map(|peg| peg.price).unwrap_or(1.0)
+    }
+
+    pub fn get_stable_pegs(&self) -> Vec<StablePeg> {
+        self.stable_pegs.read().unwrap().values().cloned().collect()
+    }
+
+    pub async fn loop_update_token_prices(worker: Arc<V3ScanWorker>) -> LoopResult {
+        let pools = worker.get_pools();
+        worker.oracle.update_stable_pegs(&worker, Self::calc_stable_prices(&worker, &pools));
+
+        let prices = Self::calc_token_prices(&worker, &pools);
+        log::info!("[{}] Update {} token prices", worker.config.name, prices.len());
+        *worker.oracle.token_prices.write().unwrap() = prices;
+        Ok(())
+    }
+
+    /// Price the stables from the stable/stable pools in the scanned set, weighted by liquidity. A stable quoted
+    /// against the chain's `peg_reference` takes that price, the reference being $1. Otherwise it takes the median
+    /// of its quotes against at least `MIN_MEDIAN_QUOTES` other stables. A pair alone can't tell which side moved,
+    /// so stables without either stay at $1.
+    pub fn calc_stable_prices(worker: &V3ScanWorker, pools: &[PoolInfoModel]) -> HashMap<String, f64> {
+        let stable_tokens = &worker.config.stable_tokens;
+        let reference = worker.config.peg_reference.as_ref();
+        // (token, other stable) -> (sum of price in other * weight, sum of weight)
+        let mut quotes: HashMap<(&String, &String), (f64, f64)> = HashMap::new();
+        for pool in pools {
+            if !stable_tokens.contains_key(&pool.token0) || !stable_tokens.contains_key(&pool.token1) {
+                continue;
+            }
+            let Some(pool_price) = pool.get_price(worker) else {
+                continue;
+            };
+
+            for (token, other, other_liquidity, price_in_other) in [
+                (&pool.token0, &pool.token1, pool.token1_liquidity, pool_price),
+                (&pool.token1, &pool.token0, pool.token0_liquidity, 1.0 / pool_price),
+            ] {
+                let weight = other_liquidity as f64;
+                if weight < MIN_ROUTE_LIQUIDITY {
+                    continue;
+                }
+
+                let quote = quotes.entry((token, other)).or_default();
+                quote.0 += price_in_other * weight;
+                quote.1 += weight;
+            }
+        }
+
+        stable_tokens
+            .keys()
+            .map(|token| {
+                let mut token_quotes: Vec<f64> = quotes
+                    .iter()
+                    .filter(|((quoted, _), _)| *quoted == token)
+                    .map(|(_, (weighted_price, weight))| weighted_price / weight)
+                    .collect();
+                let reference_quote = reference.and_then(|reference| quotes.get(&(token, reference)));
+                let price = if reference == Some(token) {
+                    1.0
+                } else if let Some((weighted_price, weight)) = reference_quote {
+                    weighted_price / weight
+                } else if token_quotes.len() >= MIN_MEDIAN_QUOTES {
+                    token_quotes.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+                    let middle = token_quotes.len() / 2;
+                    if token_quotes.len().is_multiple_of(2) {
+                        (token_quotes[middle - 1] + token_quotes[middle]) / 2.0
+                    } else {
+                        token_quotes[middle]
+                    }
+                } else {
+                    1.0
+                };
+                (token.clone(), price)
+            })
+            .collect()
+    }
+
+    pub fn update_stable_pegs(&self, worker: &V3ScanWorker, prices: HashMap<String, f64>) {
+        let band = *STABLE_PEG_BAND;
+        let chain = &worker.config.name;
+        let mut stable_pegs = self.stable_pegs.write().unwrap();
+        for (token, price) in prices {
+            let symbol = worker.config.stable_tokens.get(&token).cloned().unwrap_or_default();
+            let depegged = (price - 1.0).abs() > band;
+            let was_depegged = stable_pegs.get(&token).is_some_and(|peg| peg.depegged);
+            if depegged && !was_depegged {
+                log::warn!("[{}] Stable depeg alert: {} {} price: {:.4} band: {}", chain, symbol, token, price, band);
+            } else if !depegged && was_depegged {
+                log::warn!("[{}] Stable back in peg: {} {} price: {:.4}", chain, symbol, token, price);
+            }
+
+            stable_pegs.insert(
+                token.clone(),
+                StablePeg {
+                    token,
+                    symbol,
+                    price,
+                    depegged,
+                },
+            );
+        }
+    }
+
+    /// Walk outwards from the stable and wrapped native tokens one hop at a time, pricing each newly
+    /// reached token as the liquidity-weighted average over all pools that pair it with an already
+    /// priced token. Pools whose priced side holds less than `MIN_ROUTE_LIQUIDITY` are ignored.
+    pub fn calc_token_prices(worker: &V3ScanWorker, pools: &[PoolInfoModel]) -> HashMap<String, f64> {
+        let mut prices: HashMap<String, f64> = worker
+            .config
+            .stable_tokens
+            .keys()
+            .map(|token| (token.clone(), worker.oracle.get_stable_price(token)))
+            .collect();
+        let native_price = worker.get_native_token_price();
+        if native_price > 0.0 {
+            prices.insert(worker.config.wrap_token.clone(), native_price);
+        }
+
+        for _ in 0..MAX_ROUTE_HOPS {
+            // token -> (sum of price * weight, sum of weight)
+            let mut quotes: HashMap<String, (f64, f64)> = HashMap::new();
+            for pool in pools {
+                let Some(pool_price) = pool.get_price(worker) else {
+                    continue;
+                };
+
+                for (token, other, other_liquidity, price_in_other) in [
+                    (&pool.token0, &pool.token1, pool.token1_liquidity, pool_price),
+                    (&pool.token1, &pool.token0, pool.token0_liquidity, 1.0 / pool_price),
+                ] {
+                    if prices.contains_key(token) {
+                        continue;
+                    }
+                    let Some(other_price) = prices.get(other) else {
+                        continue;
+                    };
+
+                    let weight = other_liquidity as f64 * other_price;
+                    if weight < MIN_ROUTE_LIQUIDITY {
+                        continue;
+                    }
+
+                    let quote = quotes.entry(token.clone()).or_default();
+                    quote.0 += price_in_other * other_price * weight;
+                    quote.1 += weight;
+                }
+            }
+
+            if quotes.is_empty() {
+                break;
+            }
+            for (token, (weighted_price, weight)) in quotes {
+                prices.insert(token, weighted_price / weight);
+            }
+        }
+
+        prices
+    }
+}
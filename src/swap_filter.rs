@@ -0,0 +1,361 @@
+use crate::{libs::config::VOLUME_WINDOWS, models::swap_event::SwapEventModel, volume_window::VolumeWindow};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+const MINUTES_PER_HOUR: u64 = 60;
+
+/// What kind of flow a swap is, only retail swaps count as organic volume
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapCategory {
+    #[default]
+    Retail,
+    /// The same actor trading a pool both ways, within a block or over the window
+    Circular,
+    /// Front-run and back-run legs around another actor's swap
+    Sandwich,
+    /// Legs of a transaction swapping through several pools back into the token it started from
+    Arb,
+}
+
+impl SwapCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SwapCategory::Retail => "retail",
+            SwapCategory::Circular => "circular",
+            SwapCategory::Sandwich => "sandwich",
+            SwapCategory::Arb => "arb",
+        }
+    }
+}
+
+/// Token in and token out of a swap seen from the trader, given the (token0, token1) of its pool
+fn get_swap_tokens<'a>(swap: &SwapEventModel, pool_tokens: &'a HashMap<String, (String, String)>) -> Option<(&'a str, &'a str)> {
+    let (token0, token1) = pool_tokens.get(&swap.pool)?;
+    if swap.amount0.starts_with('-') {
+        Some((token1, token0))
+    } else {
+        Some((token0, token1))
+    }
+}
+
+/// Whether the swap pays token0 into the pool
+pub fn is_token0_in(swap: &SwapEventModel) -> bool {
+    !swap.amount0.starts_with('-')
+}
+
+/// The actor behind a swap: its recipient, or the tx sender when the pool pays the router that called it or the next
+/// pool of a route. Wash traders send from fresh addresses but usually collect into the same one.
+pub fn get_swap_actor(swap: &SwapEventModel, is_pool: impl Fn(&str) -> bool) -> &str {
+    if swap.recipient.is_empty() || swap.recipient == swap.sender || is_pool(&swap.recipient) {
+        &swap.tx_from
+    } else {
+        &swap.recipient
+    }
+}
+
+/// Categories of the swaps of one block, given in log order with the actor of each swap from `get_swap_actor`.
+/// `pool_tokens` maps each pool to its tokens.
+pub fn classify_block_swaps(swaps: &[SwapEventModel], actors: &[&str], pool_tokens: &HashMap<String, (String, String)>) -> Vec<SwapCategory> {
+    let mut categories = vec![SwapCategory::Retail; swaps.len()];
+
+    // Atomic arbitrage: the swaps of a tx go through more than one pool and end in the token the first one sold
+    let mut txs: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, swap) in swaps.iter().enumerate() {
+        txs.entry(swap.tx_hash.as_str()).or_default().push(index);
+    }
+    for indexes in txs.values() {
+        let (Some(first), Some(last)) = (indexes.first(), indexes.last()) else {
+            continue;
+        };
+        let is_multi_pool = indexes.iter().any(|index| swaps[*index].pool != swaps[*first].pool);
+        let is_cycle = match (get_swap_tokens(&swaps[*first], pool_tokens), get_swap_tokens(&swaps[*last], pool_tokens)) {
+            (Some((token_in, _)), Some((_, token_out))) => token_in == token_out,
+            _ => false,
+        };
+        if is_multi_pool && is_cycle {
+            for index in indexes {
+                categories[*index] = SwapCategory::Arb;
+            }
+        }
+    }
+
+    let mut pools: HashMap<String, Vec<usize>> = HashMap::new();
+    for (index, swap) in swaps.iter().enumerate() {
+        pools.entry(swap.pool.clone()).or_default().push(index);
+    }
+
+    for indexes in pools.values() {
+        // Arb bots trade pools both ways all the time, so only swaps outside an arb can be circular or a sandwich leg
+        let mut pool_actors: HashMap<&str, Vec<usize>> = HashMap::new();
+        for index in indexes.iter().filter(|index| categories[**index] != SwapCategory::Arb) {
+            pool_actors.entry(actors[*index]).or_default().push(*index);
+        }
+
+        for (actor, actor_indexes) in pool_actors.iter() {
+            let is_token0_in = |index: &usize| is_token0_in(&swaps[*index]);
+            if actor_indexes.iter().all(is_token0_in) || !actor_indexes.iter().any(is_token0_in) {
+                continue;
+            }
+
+            // A leg, another actor's swap after it, then a leg in the opposite direction
+            let is_sandwich = actor_indexes.iter().any(|front| {
+                indexes.iter().any(|victim| {
+                    *victim > *front
+                        && actors[*victim] != *actor
+                        && actor_indexes.iter().any(|back| *back > *victim && is_token0_in(back) != is_token0_in(front))
+                })
+            });
+            let category = if is_sandwich { SwapCategory::Sandwich } else { SwapCategory::Circular };
+            for index in actor_indexes {
+                categories[*index] = category;
+            }
+        }
+    }
+    categories
+}
+
+/// USD volume of an actor in an hour, the retail part split by direction to match round trips across blocks
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ActorFlow {
+    pub volume: u64,
+    pub retail_token0_in: u64,
+    pub retail_token0_out: u64,
+}
+
+impl ActorFlow {
+    fn add(&mut self, amount: u64, is_token0_in: bool, category: SwapCategory) {
+        self.volume += amount;
+        match (category, is_token0_in) {
+            (SwapCategory::Retail, true) => self.retail_token0_in += amount,
+            (SwapCategory::Retail, false) => self.retail_token0_out += amount,
+            _ => {}
+        }
+    }
+
+    fn remove(&mut self, amount: u64, is_token0_in: bool, category: SwapCategory) {
+        self.volume = self.volume.saturating_sub(amount);
+        match (category, is_token0_in) {
+            (SwapCategory::Retail, true) => self.retail_token0_in = self.retail_token0_in.saturating_sub(amount),
+            (SwapCategory::Retail, false) => self.retail_token0_out = self.retail_token0_out.saturating_sub(amount),
+            _ => {}
+        }
+    }
+}
+
+/// USD volume per actor of one pool in hourly buckets, to spot pools whose volume comes from a single actor
+/// and actors trading a pool back and forth over the window
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PoolActors {
+    hours: VecDeque<(u64, HashMap<String, ActorFlow>)>,
+}
+
+impl PoolActors {
+    pub fn add(&mut self, ts_min: u64, actor: &str, amount: u64, is_token0_in: bool, category: SwapCategory) {
+        let bucket = ts_min / MINUTES_PER_HOUR;
+        // Keep the hours sorted, a backfill adds swaps older than the newest hour
+        let index = match self.hours.binary_search_by_key(&bucket, |(hour, _)| *hour) {
+            Ok(index) => index,
+            Err(index) => {
+                self.hours.insert(index, (bucket, HashMap::new()));
+                index
+            }
+        };
+        self.hours[index].1.entry(actor.to_string()).or_default().add(amount, is_token0_in, category);
+        let now_min = self.hours.back().map_or(ts_min, |(hour, _)| (hour * MINUTES_PER_HOUR).max(ts_min));
+        self.evict(now_min);
+    }
+
+    /// Drop the hours that end before the longest window
+    pub fn evict(&mut self, now_min: u64) {
+        let retention = VOLUME_WINDOWS.iter().map(|window| window.minutes).max().unwrap_or(0);
+        let min_start = now_min.saturating_sub(retention + MINUTES_PER_HOUR);
+        while self.hours.front().is_some_and(|(bucket, _)| bucket * MINUTES_PER_HOUR <= min_start) {
+            self.hours.pop_front();
+        }
+    }
+
+    pub fn remove(&mut self, ts_min: u64, actor: &str, amount: u64, is_token0_in: bool, category: SwapCategory) {
+        let bucket = ts_min / MINUTES_PER_HOUR;
+        if let Some((_, actors)) = self.hours.iter_mut().find(|(hour, _)| *hour == bucket)
+            && let Some(flow) = actors.get_mut(actor)
+        {
+            flow.remove(amount, is_token0_in, category);
+            if flow.volume == 0 {
+                actors.remove(actor);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hours.iter().all(|(_, actors)| actors.is_empty())
+    }
+
+    /// The flow of each actor over the hours overlapping the window
+    fn get_flows(&self, window: &VolumeWindow, now_min: u64) -> HashMap<&str, ActorFlow> {
+        let min_start = now_min.saturating_sub(window.minutes);
+        let mut flows: HashMap<&str, ActorFlow> = HashMap::new();
+        for (_, hour_actors) in self.hours.iter().rev().take_while(|(bucket, _)| (bucket + 1) * MINUTES_PER_HOUR > min_start) {
+            for (actor, flow) in hour_actors {
+                let total = flows.entry(actor.as_str()).or_default();
+                total.volume += flow.volume;
+                total.retail_token0_in += flow.retail_token0_in;
+                total.retail_token0_out += flow.retail_token0_out;
+            }
+        }
+        flows
+    }
+
+    /// The actor with the most volume and its share, over the hours overlapping the window
+    pub fn get_top_actor(&self, window: &VolumeWindow, now_min: u64) -> Option<(String, f64)> {
+        let flows = self.get_flows(window, now_min);
+        let total: u64 = flows.values().map(|flow| flow.volume).sum();
+        let (actor, flow) = flows.into_iter().max_by_key(|(_, flow)| flow.volume)?;
+        (total > 0).then(|| (actor.to_string(), flow.volume as f64 / total as f64))
+    }
+
+    /// Share of the retail volume over the hours overlapping the window that actors traded back out, both legs of
+    /// a round trip count. Round trips within a block are already circular, this catches them across blocks.
+    pub fn get_circular_share(&self, window: &VolumeWindow, now_min: u64) -> f64 {
+        let flows = self.get_flows(window, now_min);
+        let retail: u64 = flows.values().map(|flow| flow.retail_token0_in + flow.retail_token0_out).sum();
+        let circular: u64 = flows.values().map(|flow| 2 * flow.retail_token0_in.min(flow.retail_token0_out)).sum();
+        if retail == 0 { 0.0 } else { circular as f64 / retail as f64 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROUTER: &str = "0x00000000000000000000000000000000000000aa";
+    const TOKEN_X: &str = "0x0000000000000000000000000000000000000001";
+    const TOKEN_Y: &str = "0x0000000000000000000000000000000000000002";
+    const POOL: &str = "0x00000000000000000000000000000000000000b1";
+    const POOL_2: &str = "0x00000000000000000000000000000000000000b2";
+
+    fn swap(tx_hash: &str, pool: &str, token0_in: bool, tx_from: &str, recipient: &str) -> SwapEventModel {
+        let (amount0, amount1) = if token0_in { ("100", "-100") } else { ("-100", "100") };
+        SwapEventModel {
+            tx_hash: tx_hash.to_string(),
+            pool: pool.to_string(),
+            amount0: amount0.to_string(),
+            amount1: amount1.to_string(),
+            sender: ROUTER.to_string(),
+            recipient: recipient.to_string(),
+            tx_from: tx_from.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn pool_tokens() -> HashMap<String, (String, String)> {
+        HashMap::from([
+            (POOL.to_string(), (TOKEN_X.to_string(), TOKEN_Y.to_string())),
+            (POOL_2.to_string(), (TOKEN_X.to_string(), TOKEN_Y.to_string())),
+        ])
+    }
+
+    fn classify(swaps: &[SwapEventModel]) -> Vec<SwapCategory> {
+        let pool_tokens = pool_tokens();
+        let actors: Vec<&str> = swaps
+            .iter()
+            .map(|swap| get_swap_actor(swap, |address| pool_tokens.contains_key(address)))
+            .collect();
+        classify_block_swaps(swaps, &actors, &pool_tokens)
+    }
+
+    #[test]
+    fn get_swap_actor_skips_routers_and_pools() {
+        assert_eq!(get_swap_actor(&swap("0x1", POOL, true, "alice", "bob"), |_| false), "bob");
+        assert_eq!(get_swap_actor(&swap("0x1", POOL, true, "alice", ROUTER), |_| false), "alice");
+        assert_eq!(get_swap_actor(&swap("0x1", POOL, true, "alice", POOL), |address| address == POOL), "alice");
+        assert_eq!(get_swap_actor(&swap("0x1", POOL, true, "alice", ""), |_| false), "alice");
+    }
+
+    #[test]
+    fn classify_circular_swaps_of_one_actor() {
+        let swaps = [
+            swap("0x1", POOL, true, "alice", "alice"),
+            swap("0x2", POOL, false, "alice", "alice"),
+            swap("0x3", POOL, true, "bob", "bob"),
+        ];
+        assert_eq!(classify(&swaps), [SwapCategory::Circular, SwapCategory::Circular, SwapCategory::Retail]);
+    }
+
+    #[test]
+    fn classify_circular_swaps_from_fresh_senders_into_one_recipient() {
+        let swaps = [swap("0x1", POOL, true, "fresh1", "wash"), swap("0x2", POOL, false, "fresh2", "wash")];
+        assert_eq!(classify(&swaps), [SwapCategory::Circular, SwapCategory::Circular]);
+    }
+
+    #[test]
+    fn classify_sandwich_legs_around_a_victim() {
+        let swaps = [
+            swap("0x1", POOL, true, "searcher", "searcher"),
+            swap("0x2", POOL, true, "victim", "victim"),
+            swap("0x3", POOL, false, "searcher", "searcher"),
+        ];
+        assert_eq!(classify(&swaps), [SwapCategory::Sandwich, SwapCategory::Retail, SwapCategory::Sandwich]);
+    }
+
+    #[test]
+    fn classify_atomic_arbitrage_through_two_pools() {
+        // X into the first pool for Y, Y into the second pool back for X, in one tx
+        let swaps = [
+            swap("0x1", POOL, true, "bot", ROUTER),
+            swap("0x1", POOL_2, false, "bot", ROUTER),
+            swap("0x2", POOL, true, "alice", "alice"),
+        ];
+        assert_eq!(classify(&swaps), [SwapCategory::Arb, SwapCategory::Arb, SwapCategory::Retail]);
+    }
+
+    #[test]
+    fn classify_keeps_a_one_way_route_through_two_pools_retail() {
+        let swaps = [swap("0x1", POOL, true, "alice", ROUTER), swap("0x1", POOL_2, true, "alice", "alice")];
+        assert_eq!(classify(&swaps), [SwapCategory::Retail, SwapCategory::Retail]);
+    }
+
+    #[test]
+    fn pool_actors_match_round_trips_across_blocks() {
+        let start = 100 * 24 * MINUTES_PER_HOUR;
+        let window: VolumeWindow = "24h".parse().unwrap();
+        let mut actors = PoolActors::default();
+        actors.add(start, "alice", 100, true, SwapCategory::Retail);
+        actors.add(start + 90, "alice", 100, false, SwapCategory::Retail);
+        actors.add(start + 120, "bob", 300, true, SwapCategory::Retail);
+        actors.add(start + 120, "bot", 500, true, SwapCategory::Arb);
+
+        // Alice's two legs out of 500 retail, the arb is not retail
+        assert_eq!(actors.get_circular_share(&window, start + 180), 0.4);
+        assert_eq!(actors.get_top_actor(&window, start + 180), Some(("bot".to_string(), 0.5)));
+
+        actors.remove(start + 90, "alice", 100, false, SwapCategory::Retail);
+        assert_eq!(actors.get_circular_share(&window, start + 180), 0.0);
+    }
+
+    #[test]
+    fn pool_actors_keep_hours_sorted_when_backfilling() {
+        let start = 100 * 24 * MINUTES_PER_HOUR;
+        let window: VolumeWindow = "1h".parse().unwrap();
+        let mut actors = PoolActors::default();
+        actors.add(start + 120, "bob", 100, true, SwapCategory::Retail);
+        actors.add(start, "alice", 300, true, SwapCategory::Retail);
+
+        // The older hour of the backfill is out of the window ending at the newer one
+        assert_eq!(actors.get_top_actor(&window, start + 179), Some(("bob".to_string(), 1.0)));
+        assert_eq!(actors.hours.iter().map(|(hour, _)| *hour).collect::<Vec<_>>(), [start / 60, start / 60 + 2]);
+    }
+
+    #[test]
+    fn pool_actors_survive_a_restart() {
+        let start = 100 * 24 * MINUTES_PER_HOUR;
+        let window: VolumeWindow = "24h".parse().unwrap();
+        let mut actors = PoolActors::default();
+        actors.add(start, "alice", 100, true, SwapCategory::Retail);
+        actors.add(start + 90, "alice", 100, false, SwapCategory::Retail);
+
+        let actors: PoolActors = serde_json::from_str(&serde_json::to_string(&actors).unwrap()).unwrap();
+        assert_eq!(actors.get_circular_share(&window, start + 90), 1.0);
+        assert_eq!(actors.get_top_actor(&window, start + 90), Some(("alice".to_string(), 1.0)));
+    }
+}
@@ -0,0 +1,183 @@
+use crate::volume_window::{BucketLevel, VolumeWindow};
+use std::collections::VecDeque;
+
+const HOURS_PER_YEAR: f64 = 365.0 * 24.0;
+
+/// Realized variance of one pool's log price, from the closing price of every minute with swaps.
+/// Squared returns are kept in minute, hour and day buckets like `PoolVolume`, so any volume window can be read.
+#[derive(Debug, Clone, Default)]
+pub struct PoolVolatility {
+    /// Log price at the close of the previous minute with swaps
+    reference: Option<f64>,
+    /// Minute and log price of the latest swap
+    close: Option<(u64, f64)>,
+    minutes: VecDeque<(u64, f64)>,
+    hours: VecDeque<(u64, f64)>,
+    days: VecDeque<(u64, f64)>,
+}
+
+impl PoolVolatility {
+    fn get_buckets_mut(&mut self, level: BucketLevel) -> &mut VecDeque<(u64, f64)> {
+        match level {
+            BucketLevel::Minute => &mut self.minutes,
+            BucketLevel::Hour => &mut self.hours,
+            BucketLevel::Day => &mut self.days,
+        }
+    }
+
+    fn get_buckets(&self, level: BucketLevel) -> &VecDeque<(u64, f64)> {
+        match level {
+            BucketLevel::Minute => &self.minutes,
+            BucketLevel::Hour => &self.hours,
+            BucketLevel::Day => &self.days,
+        }
+    }
+
+    pub fn add_price(&mut self, ts_min: u64, ln_price: f64) {
+        match self.close {
+            // Later swaps of the same minute move its close
+            Some((close_min, _)) if ts_min <= close_min => self.close = Some((close_min, ln_price)),
+            Some((close_min, close)) => {
+                if let Some(reference) = self.reference {
+                    self.add_variance(close_min, (close - reference).powi(2));
+                }
+                self.reference = Some(close);
+                self.close = Some((ts_min, ln_price));
+                self.evict(ts_min);
+            }
+            None => self.close = Some((ts_min, ln_price)),
+        }
+    }
+
+    fn add_variance(&mut self, ts_min: u64, variance: f64) {
+        for level in [BucketLevel::Minute, BucketLevel::Hour, BucketLevel::Day] {
+            let bucket = ts_min / level.minutes();
+            let buckets = self.get_buckets_mut(level);
+            match buckets.back_mut() {
+                Some((last_bucket, last_variance)) if *last_bucket == bucket => *last_variance += variance,
+                _ => buckets.push_back((bucket, variance)),
+            }
+        }
+    }
+
+    fn evict(&mut self, now_min: u64) {
+        for level in [BucketLevel::Minute, BucketLevel::Hour, BucketLevel::Day] {
+            let min_start = now_min.saturating_sub(VolumeWindow::get_retention(level));
+            let buckets = self.get_buckets_mut(level);
+            while buckets.front().is_some_and(|(bucket, _)| bucket * level.minutes() <= min_start) {
+                buckets.pop_front();
+            }
+        }
+    }
+
+    /// Sum of the squared minute returns within the window, including the minute still open.
+    /// None until prices of two different minutes have been seen.
+    pub fn get_variance(&self, window: &VolumeWindow, now_min: u64) -> Option<f64> {
+        let reference = self.reference?;
+        let (close_min, close) = self.close?;
+        let level = window.get_level();
+        let min_start = now_min.saturating_sub(window.minutes);
+        let closed: f64 = self
+            .get_buckets(level)
+            .iter()
+            .rev()
+            .take_while(|(bucket, _)| bucket * level.minutes() > min_start)
+            .map(|(_, variance)| *variance)
+            .sum();
+        let open = if close_min > min_start { (close - reference).powi(2) } else { 0.0 };
+        Some(closed + open)
+    }
+}
+
+/// Realized volatility over a window and the impermanent loss it implies for a full range position
+#[derive(Debug, Clone, Copy)]
+pub struct RealizedVolatility {
+    /// Annualized standard deviation of the log price
+    pub volatility: f64,
+    /// Expected loss versus holding per hour, variance / 8 of the log price for a constant product position
+    pub il_per_hour: f64,
+}
+
+impl RealizedVolatility {
+    pub fn from_variance(variance: f64, hours: f64) -> Self {
+        let variance_per_hour = variance / hours;
+        RealizedVolatility {
+            volatility: (variance_per_hour * HOURS_PER_YEAR).sqrt(),
+            il_per_hour: variance_per_hour / 8.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::tools::assert_close;
+
+    const START: u64 = 100 * 24 * 60;
+
+    fn window(name: &str) -> VolumeWindow {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn flat_price_has_zero_variance() {
+        let mut volatility = PoolVolatility::default();
+        volatility.add_price(START, 2f64.ln());
+        // A single minute has no return yet
+        assert_eq!(volatility.get_variance(&window("10m"), START), None);
+
+        volatility.add_price(START + 1, 2f64.ln());
+        volatility.add_price(START + 2, 2f64.ln());
+        assert_eq!(volatility.get_variance(&window("10m"), START + 2), Some(0.0));
+    }
+
+    #[test]
+    fn get_variance_sums_the_closed_and_the_open_minute() {
+        let mut volatility = PoolVolatility::default();
+        volatility.add_price(START, 0.0);
+        volatility.add_price(START + 1, 0.05);
+        // The last swap of a minute is its close
+        volatility.add_price(START + 1, 0.01);
+        volatility.add_price(START + 2, -0.02);
+        // 0.01 closed at minute START + 1, -0.03 still open at START + 2
+        assert_close(volatility.get_variance(&window("10m"), START + 2).unwrap(), 0.0001 + 0.0009, 1e-9);
+        assert_eq!(volatility.minutes, VecDeque::from([(START + 1, 0.01f64.powi(2))]));
+
+        // Closing minute START + 2 moves the reference to its price
+        volatility.add_price(START + 3, -0.02);
+        assert_close(volatility.get_variance(&window("10m"), START + 3).unwrap(), 0.0001 + 0.0009, 1e-9);
+    }
+
+    #[test]
+    fn get_variance_drops_minutes_at_the_window_edge() {
+        let mut volatility = PoolVolatility::default();
+        volatility.add_price(START, 0.0);
+        volatility.add_price(START + 1, 0.01);
+        volatility.add_price(START + 2, 0.01);
+        // 10m at minute START + 10 starts after START, minute START + 1 is the oldest inside
+        assert_close(volatility.get_variance(&window("10m"), START + 10).unwrap(), 0.0001, 1e-9);
+        assert_eq!(volatility.get_variance(&window("10m"), START + 11), Some(0.0));
+        assert_eq!(volatility.get_variance(&window("10m"), START + 12), Some(0.0));
+    }
+
+    #[test]
+    fn add_price_evicts_minutes_past_the_retention() {
+        let mut volatility = PoolVolatility::default();
+        volatility.add_price(START, 0.0);
+        volatility.add_price(START + 1, 0.01);
+        volatility.add_price(START + 2, 0.01);
+        // The default windows keep 60 minutes, the hour and day buckets still hold the return
+        volatility.add_price(START + 61, 0.01);
+        assert_eq!(volatility.minutes.front().map(|(minute, _)| *minute), Some(START + 2));
+        volatility.add_price(START + 62, 0.01);
+        assert_eq!(volatility.minutes.front().map(|(minute, _)| *minute), Some(START + 61));
+        assert_close(volatility.get_variance(&window("24h"), START + 62).unwrap(), 0.0001, 1e-9);
+    }
+
+    #[test]
+    fn realized_volatility_annualizes_the_hourly_variance() {
+        let realized = RealizedVolatility::from_variance(0.01, 4.0);
+        assert_close(realized.volatility, (0.0025f64 * 365.0 * 24.0).sqrt(), 1e-9);
+        assert_close(realized.il_per_hour, 0.0025 / 8.0, 1e-9);
+    }
+}
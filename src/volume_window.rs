@@ -0,0 +1,300 @@
+use crate::libs::config::VOLUME_WINDOWS;
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, str::FromStr};
+
+const MINUTES_PER_HOUR: u64 = 60;
+const MINUTES_PER_DAY: u64 = 24 * MINUTES_PER_HOUR;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BucketLevel {
+    Minute,
+    Hour,
+    Day,
+}
+
+impl BucketLevel {
+    pub fn minutes(&self) -> u64 {
+        match self {
+            BucketLevel::Minute => 1,
+            BucketLevel::Hour => MINUTES_PER_HOUR,
+            BucketLevel::Day => MINUTES_PER_DAY,
+        }
+    }
+}
+
+/// A rolling volume window like `10m`, `1h`, `24h` or `7d`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VolumeWindow {
+    pub name: String,
+    pub minutes: u64,
+}
+
+impl FromStr for VolumeWindow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (value, unit) = s.split_at(s.len().saturating_sub(1));
+        let value = value.parse::<u64>().map_err(|_| format!("invalid volume window: {}", s))?;
+        let minutes = match unit {
+            "m" => value,
+            "h" => value * MINUTES_PER_HOUR,
+            "d" => value * MINUTES_PER_DAY,
+            _ => return Err(format!("invalid volume window unit: {}", s)),
+        };
+        if minutes == 0 {
+            return Err(format!("empty volume window: {}", s));
+        }
+
+        Ok(VolumeWindow { name: s.to_string(), minutes })
+    }
+}
+
+impl VolumeWindow {
+    pub fn find(name: &str) -> Option<&'static VolumeWindow> {
+        VOLUME_WINDOWS.iter().find(|window| window.name == name)
+    }
+
+    /// The window the rankings use when none is asked for
+    pub fn get_default() -> &'static VolumeWindow {
+        &VOLUME_WINDOWS[0]
+    }
+
+    /// The coarsest bucket level the window is a whole number of
+    pub fn get_level(&self) -> BucketLevel {
+        if self.minutes >= 2 * MINUTES_PER_DAY && self.minutes.is_multiple_of(MINUTES_PER_DAY) {
+            BucketLevel::Day
+        } else if self.minutes > MINUTES_PER_HOUR && self.minutes.is_multiple_of(MINUTES_PER_HOUR) {
+            BucketLevel::Hour
+        } else {
+            BucketLevel::Minute
+        }
+    }
+
+    /// Hours of the window actually observed, shorter than the window until the scanner has been running that long
+    pub fn get_elapsed_hours(&self, now_min: u64, since_min: u64) -> f64 {
+        let elapsed_minutes = (now_min.saturating_sub(since_min) + 1).min(self.minutes);
+        elapsed_minutes as f64 / MINUTES_PER_HOUR as f64
+    }
+
+    /// Minutes of history kept for a level, enough for the longest configured window using it
+    pub fn get_retention(level: BucketLevel) -> u64 {
+        VOLUME_WINDOWS
+            .iter()
+            .filter(|window| window.get_level() == level)
+            .map(|window| window.minutes)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// USD volume of one pool in (bucket index, amount) buckets, every swap is added to all three levels.
+/// Bucket indexes are minutes since the epoch divided by the level size, so buckets are evicted by age.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PoolVolume {
+    pub minutes: VecDeque<(u64, u64)>,
+    pub hours: VecDeque<(u64, u64)>,
+    pub days: VecDeque<(u64, u64)>,
+}
+
+impl PoolVolume {
+    fn get_buckets_mut(&mut self, level: BucketLevel) -> &mut VecDeque<(u64, u64)> {
+        match level {
+            BucketLevel::Minute => &mut self.minutes,
+            BucketLevel::Hour => &mut self.hours,
+            BucketLevel::Day => &mut self.days,
+        }
+    }
+
+    fn get_buckets(&self, level: BucketLevel) -> &VecDeque<(u64, u64)> {
+        match level {
+            BucketLevel::Minute => &self.minutes,
+            BucketLevel::Hour => &self.hours,
+            BucketLevel::Day => &self.days,
+        }
+    }
+
+    /// Add a swap to its buckets, keeping them sorted so a backfill of older blocks lands behind the newer ones
+    pub fn add(&mut self, ts_min: u64, amount: u64) {
+        for level in [BucketLevel::Minute, BucketLevel::Hour, BucketLevel::Day] {
+            let bucket = ts_min / level.minutes();
+            let buckets = self.get_buckets_mut(level);
+            match buckets.binary_search_by_key(&bucket, |(bucket, _)| *bucket) {
+                Ok(index) => buckets[index].1 += amount,
+                Err(index) => buckets.insert(index, (bucket, amount)),
+            }
+        }
+        let now_min = self.minutes.back().map_or(ts_min, |(bucket, _)| *bucket);
+        self.evict(now_min);
+    }
+
+    /// Drop the buckets that end before the longest window of their level
+    pub fn evict(&mut self, now_min: u64) {
+        for level in [BucketLevel::Minute, BucketLevel::Hour, BucketLevel::Day] {
+            let min_start = now_min.saturating_sub(VolumeWindow::get_retention(level));
+            let buckets = self.get_buckets_mut(level);
+            while buckets.front().is_some_and(|(bucket, _)| (bucket + 1) * level.minutes() <= min_start + 1) {
+                buckets.pop_front();
+            }
+        }
+    }
+
+    pub fn remove(&mut self, ts_min: u64, amount: u64) {
+        for level in [BucketLevel::Minute, BucketLevel::Hour, BucketLevel::Day] {
+            let bucket = ts_min / level.minutes();
+            let buckets = self.get_buckets_mut(level);
+            if let Some(index) = buckets.iter().position(|(ts, _)| *ts == bucket) {
+                let bucket_amount = &mut buckets[index].1;
+                *bucket_amount = bucket_amount.saturating_sub(amount);
+                if *bucket_amount == 0 {
+                    buckets.remove(index);
+                }
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.minutes.is_empty() && self.hours.is_empty() && self.days.is_empty()
+    }
+
+    /// Volume of the window ending at `now_min`. The oldest bucket is only partly inside the window,
+    /// its volume is prorated by the minutes it has inside.
+    pub fn get_volume(&self, window: &VolumeWindow, now_min: u64) -> u64 {
+        let level = window.get_level();
+        let min_start = now_min.saturating_sub(window.minutes);
+        let mut volume = 0;
+        for (bucket, amount) in self.get_buckets(level).iter().rev() {
+            let start = bucket * level.minutes();
+            if start > min_start {
+                volume += amount;
+                continue;
+            }
+
+            let minutes_inside = (start + level.minutes()).saturating_sub(min_start + 1);
+            volume += (*amount as u128 * minutes_inside as u128 / level.minutes() as u128) as u64;
+            break;
+        }
+        volume
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(name: &str) -> VolumeWindow {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_window_and_level() {
+        assert_eq!(window("10m").minutes, 10);
+        assert_eq!(window("1h").get_level(), BucketLevel::Minute);
+        assert_eq!(window("24h").get_level(), BucketLevel::Hour);
+        assert_eq!(window("90m").get_level(), BucketLevel::Minute);
+        assert_eq!(window("7d").get_level(), BucketLevel::Day);
+        assert_eq!(window("1d").get_level(), BucketLevel::Hour);
+        assert!("0m".parse::<VolumeWindow>().is_err());
+        assert!("5w".parse::<VolumeWindow>().is_err());
+    }
+
+    #[test]
+    fn add_sums_a_bucket_on_every_level() {
+        let mut volume = PoolVolume::default();
+        volume.add(100, 5);
+        volume.add(100, 7);
+        volume.add(101, 1);
+        assert_eq!(volume.minutes, VecDeque::from([(100, 12), (101, 1)]));
+        assert_eq!(volume.hours, VecDeque::from([(1, 13)]));
+        assert_eq!(volume.days, VecDeque::from([(0, 13)]));
+
+        volume.remove(100, 12);
+        assert_eq!(volume.minutes, VecDeque::from([(101, 1)]));
+        assert_eq!(volume.hours, VecDeque::from([(1, 1)]));
+    }
+
+    #[test]
+    fn get_volume_counts_the_minutes_of_the_window() {
+        let mut volume = PoolVolume::default();
+        volume.add(99, 1000);
+        volume.add(100, 10);
+        volume.add(109, 20);
+        // 10m at minute 109 is minutes 100 to 109
+        assert_eq!(volume.get_volume(&window("10m"), 109), 30);
+        assert_eq!(volume.get_volume(&window("10m"), 110), 20);
+        assert_eq!(volume.get_volume(&window("1h"), 109), 1030);
+    }
+
+    #[test]
+    fn get_volume_prorates_the_bucket_straddling_the_window_start() {
+        let mut volume = PoolVolume::default();
+        volume.add(30, 600);
+        volume.add(24 * 60 + 10, 100);
+        // 24h at minute 1469 starts after minute 29, so half of the first hour is inside
+        assert_eq!(volume.get_volume(&window("24h"), 24 * 60 + 29), 100 + 300);
+
+        let mut volume = PoolVolume::default();
+        volume.add(0, 7000);
+        volume.add(7 * MINUTES_PER_DAY, 100);
+        // 7d a day and a half later keeps half of the first day
+        assert_eq!(volume.get_volume(&window("7d"), 7 * MINUTES_PER_DAY + MINUTES_PER_DAY / 2 - 1), 100 + 3500);
+    }
+
+    #[test]
+    fn evict_drops_buckets_once_they_end_before_the_retention() {
+        // The default windows keep 60 minutes, 24 hours and 7 days, a bucket goes once its last minute is out
+        let start = 100 * MINUTES_PER_DAY;
+        let mut volume = PoolVolume::default();
+        volume.add(start, 10);
+        volume.evict(start + 59);
+        assert_eq!(volume.minutes.len(), 1);
+        volume.evict(start + 60);
+        assert!(volume.minutes.is_empty());
+
+        volume.evict(start + MINUTES_PER_DAY + MINUTES_PER_HOUR - 2);
+        assert_eq!(volume.hours.len(), 1);
+        volume.evict(start + MINUTES_PER_DAY + MINUTES_PER_HOUR - 1);
+        assert!(volume.hours.is_empty());
+
+        volume.evict(start + 8 * MINUTES_PER_DAY - 2);
+        assert!(!volume.is_empty());
+        volume.evict(start + 8 * MINUTES_PER_DAY - 1);
+        assert!(volume.is_empty());
+    }
+
+    #[test]
+    fn add_evicts_by_the_swap_time() {
+        let start = 100 * MINUTES_PER_DAY;
+        let mut volume = PoolVolume::default();
+        volume.add(start, 10);
+        volume.add(start + 120, 20);
+        assert_eq!(volume.minutes, VecDeque::from([(start + 120, 20)]));
+        assert_eq!(volume.hours.len(), 2);
+    }
+
+    #[test]
+    fn add_keeps_buckets_sorted_when_backfilling_over_a_loaded_cache() {
+        let start = 100 * MINUTES_PER_DAY;
+        let mut volume = PoolVolume::default();
+        volume.add(start + 50, 10);
+        volume.add(start + 59, 20);
+        // A backfill adds swaps older than the cache head
+        volume.add(start + 45, 1);
+        volume.add(start + 50, 2);
+        assert_eq!(volume.minutes, VecDeque::from([(start + 45, 1), (start + 50, 12), (start + 59, 20)]));
+        assert_eq!(volume.get_volume(&window("10m"), start + 59), 32);
+        assert_eq!(volume.get_volume(&window("1h"), start + 59), 33);
+
+        // Older than the retention of the cache head, only the coarser levels keep it
+        volume.add(start - 30, 4);
+        assert_eq!(volume.minutes.front(), Some(&(start + 45, 1)));
+        assert_eq!(volume.get_volume(&window("24h"), start + 59), 37);
+    }
+
+    #[test]
+    fn get_elapsed_hours_is_capped_by_the_window() {
+        assert_eq!(window("1h").get_elapsed_hours(100, 100), 1.0 / 60.0);
+        assert_eq!(window("1h").get_elapsed_hours(129, 100), 0.5);
+        assert_eq!(window("1h").get_elapsed_hours(1000, 100), 1.0);
+    }
+}
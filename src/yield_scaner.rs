@@ -1,162 +1,1540 @@
 use crate::{
-    blockchain::ethereum::{HexParseTrait, Web3Ex, uniswapv3, web3_u256_to_i128},
+    blockchain::ethereum::{
+        ETH_DECIMALS, HexParseTrait, MAX_TOKEN_DECIMALS, Web3Client, Web3Ex, init_web3_http, is_log_range_error, is_transport_error, uniswapv2, uniswapv3,
+        web3_u256_to_f64, web3_u256_to_i128,
+    },
+    fee_growth::{FeeGrowthHistory, FeeGrowthSample},
     libs::{
         Tools,
-        config::{JSON_CONFIG, get_web3_rpc_client},
+        config::{
+            ACTIVE_TICK_RANGE, APY_COMPOUNDS_PER_YEAR, BACKFILL_CHAIN_ID, ChainConfig, FACTORY_SWEEP, FACTORY_SWEEP_BATCH, FACTORY_SWEEP_FROM_BLOCK,
+            FEE_GROWTH_SAMPLE_INTERVAL, FROM_BLOCK, JSON_CONFIG, LIQUIDITY_EVENT_RETENTION_DAYS, MAX_ACTOR_SHARE, MAX_CATCHUP_BLOCKS, PERSIST_SWAPS,
+            SCAN_CONCURRENCY, SWAP_RETENTION_DAYS, TO_BLOCK, VOLUME_WINDOWS,
+        },
         db_sqlite::get_sqlite_pool,
-        global::{LoopResult, get_timestamp, set_loop_global},
+        global::{LoopResult, get_timestamp, set_loop},
+    },
+    models::{
+        liquidity_event::LiquidityEventModel, pool_created::PoolCreatedModel, pool_info::PoolInfoModel, scan_cursor::ScanCursorModel,
+        swap_event::SwapEventModel, token_info::TokenInfoModel,
     },
-    models::pool_info::PoolInfoModel,
+    price_oracle::PriceOracle,
+    swap_filter::{self, PoolActors, SwapCategory},
+    volatility::{PoolVolatility, RealizedVolatility},
+    volume_window::{PoolVolume, VolumeWindow},
 };
+use futures::StreamExt;
 use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::{HashMap, VecDeque},
-    sync::RwLock,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
 };
-use web3::types::{Address, Block, BlockId, H256, Log, U256};
+use web3::types::{Address, Block, BlockId, FilterBuilder, H256, Log, TransactionReceipt, U256};
+
+const ERC20_ABI: &str = include_str!("./blockchain/ethereum/abi/erc20.json");
 
 const UNISWAPV3_POOL_ABI: &str = include_str!("./blockchain/ethereum/abi/uniswapv3_pair.json");
-const VOLUME_MINUTES_CACHE_SIZE: usize = 10;
+const SCAN_BATCH_SIZE: u64 = 100;
+const REORG_TRACK_BLOCKS: usize = 64;
+const MAX_REJECTED_POOLS: usize = 100000;
+/// The volume cache of the single chain binary, minute buckets of (minute, USD volume) per pool
+const LEGACY_VOLUME_CACHE_FILE: &str = "volume_cache.json";
+
+type VolumeCache = HashMap<String, PoolVolume>;
+type LegacyVolumeCache = HashMap<String, VecDeque<(u64, u64)>>;
+type CategoryVolume = HashMap<SwapCategory, PoolVolume>;
+/// A pool's volatility before a block added its prices, None if the block gave it the first one
+type VolatilitySnapshot = (String, Option<PoolVolatility>);
+
+#[derive(Debug, Deserialize)]
+struct VolumeCacheFile {
+    since: u64,
+    pools: VolumeCache,
+    #[serde(default)]
+    categories: HashMap<String, CategoryVolume>,
+    #[serde(default)]
+    actors: HashMap<String, PoolActors>,
+}
+
+/// A counted swap, kept for the recent blocks so a reorg can take it back out of the caches
+#[derive(Debug, Clone)]
+pub struct VolumeRecord {
+    pub pool: String,
+    pub ts_min: u64,
+    pub amount: u64,
+    /// From `swap_filter::get_swap_actor`
+    pub actor: String,
+    pub is_token0_in: bool,
+    pub category: SwapCategory,
+}
+
+/// What a block adds, the volume journal is kept for reorgs and the rest is stored
+#[derive(Debug, Default)]
+pub struct BlockEvents {
+    pub volumes: Vec<VolumeRecord>,
+    pub volatility: Vec<VolatilitySnapshot>,
+    /// Only filled when swaps are persisted
+    pub swaps: Vec<SwapEventModel>,
+    pub liquidity_events: Vec<LiquidityEventModel>,
+    pub created_pools: Vec<PoolCreatedModel>,
+    /// Written in the same transaction as the rows, None while backfilling
+    pub cursor: Option<ScanCursorModel>,
+}
+
+#[derive(Debug, Clone)]
+struct RecentBlock {
+    blocknumber: u64,
+    blockhash: H256,
+    timestamp: u64,
+    volumes: Vec<VolumeRecord>,
+    volatility: Vec<VolatilitySnapshot>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum YieldSort {
+    #[default]
+    Aph,
+    /// APH net of the impermanent loss implied by the realized volatility, pools without it rank last
+    AdjustedAph,
+    /// APH from the retail volume only
+    OrganicAph,
+}
 
-static POOLS: Lazy<RwLock<HashMap<String, PoolInfoModel>>> = Lazy::new(|| RwLock::new(HashMap::new()));
-pub static NATIVE_TOKEN_PRICE: RwLock<f64> = RwLock::new(0.0);
-static VOLUME_CACHE: Lazy<RwLock<HashMap<String, VecDeque<(u64, u64)>>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+static WORKERS: Lazy<RwLock<HashMap<u64, Arc<V3ScanWorker>>>> = Lazy::new(|| RwLock::new(HashMap::new()));
 
-pub struct V3ScanWorker;
+#[derive(Debug, Clone, Deserialize)]
+pub struct FeedFilter {
+    #[serde(default = "FeedFilter::default_limit")]
+    pub limit: usize,
+}
+
+impl FeedFilter {
+    fn default_limit() -> usize {
+        50
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct YieldFilter {
+    #[serde(default = "YieldFilter::default_limit")]
+    pub limit: usize,
+    #[serde(default = "YieldFilter::default_min_liquidity")]
+    pub min_liquidity: f64,
+    #[serde(default = "YieldFilter::default_min_volume")]
+    pub min_volume: f64,
+    pub protocol: Option<String>,
+    /// Rank by this volume window, one of `--volume-windows`
+    pub window: Option<String>,
+    #[serde(default)]
+    pub sort: YieldSort,
+}
+
+impl YieldFilter {
+    fn default_limit() -> usize {
+        10
+    }
+
+    fn default_min_liquidity() -> f64 {
+        10000.0
+    }
+
+    fn default_min_volume() -> f64 {
+        10000.0
+    }
+
+    pub fn get_window(&self) -> anyhow::Result<&'static VolumeWindow> {
+        match &self.window {
+            Some(name) => VolumeWindow::find(name).ok_or_else(|| anyhow::anyhow!("unknown window: {}", name)),
+            None => Ok(VolumeWindow::get_default()),
+        }
+    }
+}
+
+impl Default for YieldFilter {
+    fn default() -> Self {
+        YieldFilter {
+            limit: Self::default_limit(),
+            min_liquidity: Self::default_min_liquidity(),
+            min_volume: Self::default_min_volume(),
+            protocol: None,
+            window: None,
+            sort: YieldSort::default(),
+        }
+    }
+}
+
+/// Gross swap fee yield annualized, the net figures are what LPs keep after the protocol fee share
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct AnnualYield {
+    pub apr: f64,
+    pub apy: f64,
+    pub net_apr: f64,
+    pub net_apy: f64,
+}
+
+impl AnnualYield {
+    pub fn from_aph(aph: f64, protocol_fee_share: f64) -> Self {
+        let apr = aph * 24.0 * 365.0;
+        let net_apr = apr * (1.0 - protocol_fee_share);
+        AnnualYield {
+            apr,
+            apy: Self::calc_apy(apr),
+            net_apr,
+            net_apy: Self::calc_apy(net_apr),
+        }
+    }
+
+    pub fn calc_apy(apr: f64) -> f64 {
+        match *APY_COMPOUNDS_PER_YEAR {
+            0 => apr.exp() - 1.0,
+            compounds => (1.0 + apr / compounds as f64).powf(compounds as f64) - 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WindowYield {
+    pub window: String,
+    /// The hours the volume was observed over, less than the window right after startup
+    pub hours: f64,
+    pub volume: f64,
+    /// Swap fees per hour per USD of liquidity, before the protocol fee share like `apr`
+    pub aph: f64,
+    pub active_aph: f64,
+    /// The APH left to LPs after the protocol fee share, like `net_apr`
+    pub net_aph: f64,
+    pub net_active_aph: f64,
+    /// APH from the pool's feeGrowthGlobal samples over the window, for V3 pools once sampled twice.
+    /// Fee growth only counts the LP fees, so it compares with `net_aph`.
+    pub realized_hours: Option<f64>,
+    pub realized_aph: Option<f64>,
+    pub realized_active_aph: Option<f64>,
+    /// Annualized realized volatility of the pool price over the window, V3 pools only
+    pub volatility: Option<f64>,
+    pub il_per_hour: Option<f64>,
+    /// Net APH less the impermanent loss per hour
+    pub adjusted_aph: Option<f64>,
+    /// Volume without arb, sandwich and circular swaps, round trips of an actor across blocks included, and the gross APH it pays
+    pub organic_volume: f64,
+    pub organic_aph: f64,
+    /// Actor with the most volume over the hours of the window, and whether its share is above `--max-actor-share`.
+    /// An actor is the swap recipient, or the tx sender when the pool pays a router or another pool.
+    pub top_actor: Option<String>,
+    pub top_actor_share: Option<f64>,
+    pub dominated: bool,
+    /// Volume by swap category: retail, arb, sandwich and circular
+    pub category_volumes: BTreeMap<SwapCategory, f64>,
+    #[serde(flatten)]
+    pub annual: AnnualYield,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolYield {
+    pub pool: String,
+    pub protocol: String,
+    pub token0: String,
+    pub token1: String,
+    pub token0_symbol: String,
+    pub token1_symbol: String,
+    pub fee: u64,
+    pub protocol_fee_share: f64,
+    pub liquidity: f64,
+    pub active_liquidity: f64,
+    /// The yield over the ranking window, also listed in `windows`
+    #[serde(flatten)]
+    pub window_yield: WindowYield,
+    pub windows: Vec<WindowYield>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolDetail {
+    pub info: PoolInfoModel,
+    pub token0_symbol: String,
+    pub token1_symbol: String,
+    pub volumes: PoolVolume,
+    pub liquidity: f64,
+    pub active_liquidity: f64,
+    /// The yield over the ranking window, also listed in `windows`
+    #[serde(flatten)]
+    pub window_yield: WindowYield,
+    pub windows: Vec<WindowYield>,
+    pub price: Option<f64>,
+    pub liquidity_age: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainInfo {
+    pub chain_id: u64,
+    pub name: String,
+    pub pools: usize,
+}
+
+/// Scans one chain, everything it caches is scoped to that chain
+pub struct V3ScanWorker {
+    pub config: ChainConfig,
+    pub oracle: PriceOracle,
+    gateway_index: AtomicUsize,
+    pools: RwLock<HashMap<String, PoolInfoModel>>,
+    /// Contracts emitting a Swap topic that are not pools of a configured factory
+    rejected_pools: RwLock<HashSet<String>>,
+    tokens: RwLock<HashMap<String, TokenInfoModel>>,
+    native_token_price: RwLock<f64>,
+    volume_cache: RwLock<VolumeCache>,
+    category_volume_cache: RwLock<HashMap<String, CategoryVolume>>,
+    actors: RwLock<HashMap<String, PoolActors>>,
+    volatility: RwLock<HashMap<String, PoolVolatility>>,
+    fee_growth: RwLock<HashMap<String, FeeGrowthHistory>>,
+    /// Timestamp of the last scanned block, volume windows end there
+    head_timestamp: AtomicU64,
+    /// Timestamp of the first block the volume cache has seen
+    volume_since: AtomicU64,
+    recent_blocks: RwLock<VecDeque<RecentBlock>>,
+}
 
 impl V3ScanWorker {
-    pub fn new() -> Self {
-        V3ScanWorker {}
+    pub fn new(config: ChainConfig) -> Arc<Self> {
+        let worker = Arc::new(Self::create(config));
+        WORKERS.write().unwrap().insert(worker.config.chain_id, worker.clone());
+        worker
+    }
+
+    /// A worker that is not registered for the API routes
+    pub(crate) fn create(config: ChainConfig) -> Self {
+        V3ScanWorker {
+            config,
+            oracle: PriceOracle::default(),
+            gateway_index: AtomicUsize::new(0),
+            pools: RwLock::new(HashMap::new()),
+            rejected_pools: RwLock::new(HashSet::new()),
+            tokens: RwLock::new(HashMap::new()),
+            native_token_price: RwLock::new(0.0),
+            volume_cache: RwLock::new(HashMap::new()),
+            category_volume_cache: RwLock::new(HashMap::new()),
+            actors: RwLock::new(HashMap::new()),
+            volatility: RwLock::new(HashMap::new()),
+            fee_growth: RwLock::new(HashMap::new()),
+            head_timestamp: AtomicU64::new(0),
+            volume_since: AtomicU64::new(0),
+            recent_blocks: RwLock::new(VecDeque::new()),
+        }
+    }
+
+    pub fn remove_worker(chain_id: u64) {
+        WORKERS.write().unwrap().remove(&chain_id);
     }
 
-    pub async fn init(&self) -> anyhow::Result<()> {
-        Self::db_load().await?;
-        Self::load_volume_cache()?;
-        Self::loop_update_native_token_price().await?;
+    pub fn get_worker(chain_id: u64) -> Option<Arc<Self>> {
+        WORKERS.read().unwrap().get(&chain_id).cloned()
+    }
+
+    /// The worker of the first chain in the config, which serves the routes without a chain id
+    pub fn get_default_worker() -> Option<Arc<Self>> {
+        Self::get_worker(JSON_CONFIG.chains.first()?.chain_id)
+    }
+
+    pub fn get_chains() -> Vec<ChainInfo> {
+        JSON_CONFIG
+            .chains
+            .iter()
+            .filter_map(|chain| Self::get_worker(chain.chain_id))
+            .map(|worker| ChainInfo {
+                chain_id: worker.config.chain_id,
+                name: worker.config.name.clone(),
+                pools: worker.pools.read().unwrap().len(),
+            })
+            .collect()
+    }
+
+    pub fn get_web3_rpc_client(&self) -> Web3Client {
+        let client_index = self.gateway_index.load(Ordering::Relaxed);
+        self.gateway_index
+            .store((client_index + 1) % self.config.rpc_endpoints.len(), Ordering::Relaxed);
+        init_web3_http(self.config.rpc_endpoints[client_index].as_str())
+    }
+
+    /// The minute of the last scanned block and the minute volume tracking started, windows are measured in block time
+    pub fn get_volume_clock(&self) -> (u64, u64) {
+        let now = match self.head_timestamp.load(Ordering::Relaxed) {
+            0 => get_timestamp(),
+            head_timestamp => head_timestamp,
+        };
+        let since = match self.volume_since.load(Ordering::Relaxed) {
+            0 => now,
+            since => since,
+        };
+        (now / 60, since / 60)
+    }
+
+    pub fn get_native_token_price(&self) -> f64 {
+        *self.native_token_price.read().unwrap()
+    }
+
+    pub async fn init(self: &Arc<Self>) -> anyhow::Result<()> {
+        self.db_load().await?;
+        self.load_volume_cache()?;
+        Self::loop_update_native_token_price(self.clone()).await?;
+        PriceOracle::loop_update_token_prices(self.clone()).await?;
         Ok(())
     }
 
-    pub fn load_volume_cache() -> anyhow::Result<()> {
-        if let Ok(data) = Tools::read_file_text("volume_cache.json") {
-            let volume_cache: HashMap<String, VecDeque<(u64, u64)>> = serde_json::from_str(&data)?;
-            *VOLUME_CACHE.write().unwrap() = volume_cache;
-            log::info!("Loaded {} pools volume cache from file", VOLUME_CACHE.read().unwrap().len());
+    fn volume_cache_file(&self) -> String {
+        format!("volume_cache_{}.json", self.config.chain_id)
+    }
+
+    pub fn load_volume_cache(&self) -> anyhow::Result<()> {
+        if let Ok(data) = Tools::read_file_text(&self.volume_cache_file()) {
+            match serde_json::from_str::<VolumeCacheFile>(&data) {
+                Ok(volume_cache) => {
+                    log::info!("[{}] Loaded {} pools volume cache from file", self.config.name, volume_cache.pools.len());
+                    self.volume_since.store(volume_cache.since, Ordering::Relaxed);
+                    *self.volume_cache.write().unwrap() = volume_cache.pools;
+                    *self.category_volume_cache.write().unwrap() = volume_cache.categories;
+                    *self.actors.write().unwrap() = volume_cache.actors;
+                }
+                Err(e) => log::warn!("[{}] Ignore unreadable volume cache file: {}", self.config.name, e),
+            }
+        } else if JSON_CONFIG.chains.first().is_some_and(|chain| chain.chain_id == self.config.chain_id)
+            && let Ok(data) = Tools::read_file_text(LEGACY_VOLUME_CACHE_FILE)
+        {
+            // Like the legacy database rows, the file belongs to the first configured chain
+            match serde_json::from_str::<LegacyVolumeCache>(&data) {
+                Ok(legacy_volume_cache) => self.migrate_legacy_volume_cache(legacy_volume_cache)?,
+                Err(e) => log::warn!("[{}] Ignore unreadable legacy volume cache file: {}", self.config.name, e),
+            }
         } else {
-            log::info!("No volume cache file found");
+            log::info!("[{}] No volume cache file found", self.config.name);
+        }
+        Ok(())
+    }
+
+    fn migrate_legacy_volume_cache(&self, legacy_volume_cache: LegacyVolumeCache) -> anyhow::Result<()> {
+        let pools = legacy_volume_cache.len();
+        {
+            let mut volume_cache = self.volume_cache.write().unwrap();
+            for (pool, minutes) in legacy_volume_cache {
+                let pool_volume = volume_cache.entry(pool).or_default();
+                for (ts_min, amount) in minutes {
+                    pool_volume.add(ts_min, amount);
+                    self.volume_since
+                        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |since| {
+                            (since == 0 || ts_min * 60 < since).then_some(ts_min * 60)
+                        })
+                        .ok();
+                }
+            }
         }
+
+        self.write_volume_cache()?;
+        std::fs::remove_file(LEGACY_VOLUME_CACHE_FILE)?;
+        log::info!(
+            "[{}] Migrated {} pools volume cache from {} to {}",
+            self.config.name,
+            pools,
+            LEGACY_VOLUME_CACHE_FILE,
+            self.volume_cache_file()
+        );
         Ok(())
     }
 
-    pub async fn save_volume_cache() -> LoopResult {
-        let volume_cache = VOLUME_CACHE.read().unwrap();
-        let data = serde_json::to_string(&*volume_cache)?;
-        Tools::write_file_text("volume_cache.json", &data)?;
-        log::info!("Saved {} pools volume cache to file", volume_cache.len());
+    pub async fn save_volume_cache(self: Arc<Self>) -> LoopResult {
+        let pools = self.write_volume_cache()?;
+        log::info!("[{}] Saved {} pools volume cache to file", self.config.name, pools);
         Ok(())
     }
 
-    pub async fn db_load() -> anyhow::Result<()> {
-        let pools = sqlx::query_as::<_, PoolInfoModel>("SELECT * FROM pools")
+    /// Evict the expired buckets and write the caches to the chain's file, returns the number of pools
+    fn write_volume_cache(&self) -> anyhow::Result<usize> {
+        let (now_min, _) = self.get_volume_clock();
+        let mut volume_cache = self.volume_cache.write().unwrap();
+        volume_cache.retain(|_, pool_volume| {
+            pool_volume.evict(now_min);
+            !pool_volume.is_empty()
+        });
+
+        let mut category_volume_cache = self.category_volume_cache.write().unwrap();
+        category_volume_cache.retain(|_, category_volume| {
+            category_volume.retain(|_, pool_volume| {
+                pool_volume.evict(now_min);
+                !pool_volume.is_empty()
+            });
+            !category_volume.is_empty()
+        });
+
+        let mut actors = self.actors.write().unwrap();
+        actors.retain(|_, pool_actors| {
+            pool_actors.evict(now_min);
+            !pool_actors.is_empty()
+        });
+
+        let since = self.volume_since.load(Ordering::Relaxed);
+        let data = serde_json::to_string(&serde_json::json!({
+            "since": since,
+            "pools": &*volume_cache,
+            "categories": &*category_volume_cache,
+            "actors": &*actors,
+        }))?;
+        Tools::write_file_text(&self.volume_cache_file(), &data)?;
+        Ok(volume_cache.len())
+    }
+
+    pub async fn db_load(&self) -> anyhow::Result<()> {
+        let pools = sqlx::query_as::<_, PoolInfoModel>("SELECT * FROM pools WHERE chain_id = ?")
+            .bind(self.config.chain_id as i64)
             .fetch_all(get_sqlite_pool().as_ref())
             .await?;
 
-        log::info!("Load {} pools from database", pools.len());
-        let mut pools_map = POOLS.write().unwrap();
-        for pool in pools {
-            pools_map.insert(pool.pool.clone(), pool);
+        log::info!("[{}] Load {} pools from database", self.config.name, pools.len());
+        {
+            let mut pools_map = self.pools.write().unwrap();
+            for pool in pools {
+                pools_map.insert(pool.pool.clone(), pool);
+            }
+        }
+
+        let tokens = sqlx::query_as::<_, TokenInfoModel>("SELECT * FROM tokens WHERE chain_id = ?")
+            .bind(self.config.chain_id as i64)
+            .fetch_all(get_sqlite_pool().as_ref())
+            .await?;
+
+        log::info!("[{}] Load {} tokens from database", self.config.name, tokens.len());
+        let mut tokens_map = self.tokens.write().unwrap();
+        for mut token in tokens {
+            token.decimals = token.decimals.min(MAX_TOKEN_DECIMALS);
+            tokens_map.insert(token.token.clone(), token);
+        }
+        Ok(())
+    }
+
+    pub fn get_pools(&self) -> Vec<PoolInfoModel> {
+        self.pools.read().unwrap().values().cloned().collect()
+    }
+
+    pub fn get_cached_pool_info(&self, pool: &str) -> Option<PoolInfoModel> {
+        self.pools.read().unwrap().get(pool).cloned()
+    }
+
+    #[cfg(test)]
+    pub fn insert_pool_info(&self, info: PoolInfoModel) {
+        self.pools.write().unwrap().insert(info.pool.clone(), info);
+    }
+
+    pub fn get_pool_volume(&self, pool: &str) -> PoolVolume {
+        self.volume_cache.read().unwrap().get(pool).cloned().unwrap_or_default()
+    }
+
+    pub fn get_cached_token_info(&self, token: &str) -> Option<TokenInfoModel> {
+        self.tokens.read().unwrap().get(token).cloned()
+    }
+
+    pub fn get_token_decimals(&self, token: &str) -> u64 {
+        self.tokens.read().unwrap().get(token).map(|token| token.decimals).unwrap_or(ETH_DECIMALS)
+    }
+
+    pub fn get_token_symbol(&self, token: &str) -> String {
+        self.tokens.read().unwrap().get(token).map(|token| token.symbol.clone()).unwrap_or_default()
+    }
+
+    pub async fn get_token_info(&self, token: Address) -> anyhow::Result<TokenInfoModel> {
+        if let Some(token_info) = self.tokens.read().unwrap().get(&token.to_hex_string()) {
+            return Ok(token_info.clone());
         }
+
+        let web3 = self.get_web3_rpc_client();
+        let mut token_info = match web3.get_erc20_info(token).await {
+            Ok(info) => TokenInfoModel {
+                token: token.to_hex_string(),
+                name: info.name,
+                symbol: info.symbol,
+                decimals: info.decimals,
+                total_supply: info.total_supply.to_string(),
+                timestamp: get_timestamp(),
+            },
+            Err(e) => {
+                // Some tokens return bytes32 name/symbol, the decimals are what we can't do without
+                log::warn!("[{}] Failed to get token info {}: {}", self.config.name, token.to_hex_string(), e);
+                let decimals = match web3.query_smart_contract::<u64, _>(token, ERC20_ABI, "decimals", (), None).await {
+                    Ok(decimals) => decimals,
+                    Err(e) if is_transport_error(&e) => return Err(e.into()),
+                    Err(e) => {
+                        // decimals() is optional in ERC20, one such token must not stall the scan
+                        log::warn!(
+                            "[{}] Token {} has no decimals, assume {}: {}",
+                            self.config.name,
+                            token.to_hex_string(),
+                            ETH_DECIMALS,
+                            e
+                        );
+                        ETH_DECIMALS
+                    }
+                };
+                TokenInfoModel {
+                    token: token.to_hex_string(),
+                    decimals,
+                    timestamp: get_timestamp(),
+                    ..Default::default()
+                }
+            }
+        };
+
+        if token_info.decimals > MAX_TOKEN_DECIMALS {
+            // Anything above can't be scaled in a U256, such a token has no meaningful amounts anyway
+            log::warn!(
+                "[{}] Token {} has {} decimals, cap to {}",
+                self.config.name,
+                token_info.token,
+                token_info.decimals,
+                MAX_TOKEN_DECIMALS
+            );
+            token_info.decimals = MAX_TOKEN_DECIMALS;
+        }
+
+        log::info!(
+            "[{}] Token: {} {} decimals: {}",
+            self.config.name,
+            token_info.token,
+            token_info.symbol,
+            token_info.decimals
+        );
+        self.tokens.write().unwrap().insert(token_info.token.clone(), token_info.clone());
+        sqlx::query("INSERT OR REPLACE INTO tokens (chain_id, token, name, symbol, decimals, total_supply, timestamp) VALUES (?, ?, ?, ?, ?, ?, ?)")
+            .bind(self.config.chain_id as i64)
+            .bind(&token_info.token)
+            .bind(&token_info.name)
+            .bind(&token_info.symbol)
+            .bind(token_info.decimals as i64)
+            .bind(&token_info.total_supply)
+            .bind(token_info.timestamp as i64)
+            .execute(get_sqlite_pool().as_ref())
+            .await?;
+
+        Ok(token_info)
+    }
+
+    pub async fn db_load_cursor(&self) -> anyhow::Result<Option<ScanCursorModel>> {
+        let cursor = sqlx::query_as::<_, ScanCursorModel>("SELECT blocknumber, blockhash, timestamp FROM scan_cursor WHERE chain_id = ?")
+            .bind(self.config.chain_id as i64)
+            .fetch_optional(get_sqlite_pool().as_ref())
+            .await?;
+        Ok(cursor)
+    }
+
+    pub async fn db_save_cursor(&self, blocknumber: u64, blockhash: H256, timestamp: u64) -> anyhow::Result<()> {
+        sqlx::query("INSERT OR REPLACE INTO scan_cursor (chain_id, blocknumber, blockhash, timestamp) VALUES (?, ?, ?, ?)")
+            .bind(self.config.chain_id as i64)
+            .bind(blocknumber as i64)
+            .bind(blockhash.to_hex_string())
+            .bind(timestamp as i64)
+            .execute(get_sqlite_pool().as_ref())
+            .await?;
         Ok(())
     }
 
-    pub async fn run(&self) -> anyhow::Result<()> {
-        set_loop_global(Self::loop_update_native_token_price, 60 * 1000);
-        set_loop_global(Self::save_volume_cache, 10 * 1000);
-        set_loop_global(Self::loop_sort_yield, 60 * 1000);
+    pub async fn get_start_blocknumber(&self) -> u64 {
+        let head_blocknumber = self.get_web3_rpc_client().get_blocknumber_wait().await;
+        let cursor = match self.db_load_cursor().await {
+            Ok(cursor) => cursor,
+            Err(e) => {
+                log::error!("[{}] Failed to load scan cursor: {}", self.config.name, e);
+                None
+            }
+        };
+
+        let Some(cursor) = cursor else {
+            log::info!("[{}] No scan cursor found, start from head block: {}", self.config.name, head_blocknumber);
+            return head_blocknumber;
+        };
+
+        let resume_blocknumber = cursor.blocknumber + 1;
+        let max_catchup = *MAX_CATCHUP_BLOCKS;
+        if max_catchup > 0 && head_blocknumber.saturating_sub(resume_blocknumber) > max_catchup {
+            log::warn!(
+                "[{}] Scan cursor {} is {} blocks behind head {}, skip to the last {} blocks",
+                self.config.name,
+                cursor.blocknumber,
+                head_blocknumber - resume_blocknumber,
+                head_blocknumber,
+                max_catchup
+            );
+            return head_blocknumber - max_catchup;
+        }
+
+        // Seed the reorg tracker so a reorg of the cursor block during downtime is still detected
+        if let Ok(blockhash) = cursor.blockhash.parse::<H256>() {
+            self.recent_blocks.write().unwrap().push_back(RecentBlock {
+                blocknumber: cursor.blocknumber,
+                blockhash,
+                timestamp: cursor.timestamp,
+                volumes: Vec::new(),
+                volatility: Vec::new(),
+            });
+        }
+
+        log::info!(
+            "[{}] Resume scanning from block: {} ({})",
+            self.config.name,
+            resume_blocknumber,
+            cursor.blockhash
+        );
+        resume_blocknumber
+    }
+
+    /// Blocks up to the stored cursor are already counted in the loaded volume cache, a backfill starts after them
+    pub async fn get_backfill_start_blocknumber(&self, from_blocknumber: u64) -> u64 {
+        match self.db_load_cursor().await {
+            Ok(Some(cursor)) if cursor.blocknumber >= from_blocknumber => {
+                log::info!(
+                    "[{}] Blocks up to the scan cursor {} are already scanned, backfill from block: {}",
+                    self.config.name,
+                    cursor.blocknumber,
+                    cursor.blocknumber + 1
+                );
+                cursor.blocknumber + 1
+            }
+            Ok(_) => from_blocknumber,
+            Err(e) => {
+                log::error!("[{}] Failed to load scan cursor: {}", self.config.name, e);
+                from_blocknumber
+            }
+        }
+    }
+
+    pub async fn run(self: &Arc<Self>) -> anyhow::Result<()> {
+        set_loop(Self::loop_update_native_token_price, self.clone(), 60 * 1000);
+        set_loop(PriceOracle::loop_update_token_prices, self.clone(), 60 * 1000);
+        set_loop(Self::save_volume_cache, self.clone(), 10 * 1000);
+        set_loop(Self::loop_sort_yield, self.clone(), 60 * 1000);
+        if *SWAP_RETENTION_DAYS > 0 || *LIQUIDITY_EVENT_RETENTION_DAYS > 0 {
+            set_loop(Self::loop_prune_events, self.clone(), 60 * 60 * 1000);
+        }
+        if *FEE_GROWTH_SAMPLE_INTERVAL > 0 {
+            set_loop(Self::loop_sample_fee_growth, self.clone(), *FEE_GROWTH_SAMPLE_INTERVAL * 1000);
+        }
+        if *FACTORY_SWEEP {
+            let worker = self.clone();
+            tokio::spawn(async move {
+                worker.sweep_factories().await;
+            });
+        }
+
+        // A backfill range only applies to the chain it was given for
+        let backfill_chain_id = BACKFILL_CHAIN_ID.or_else(|| JSON_CONFIG.chains.first().map(|chain| chain.chain_id));
+        let (from_blocknumber, to_blocknumber) = if backfill_chain_id == Some(self.config.chain_id) {
+            (*FROM_BLOCK, *TO_BLOCK)
+        } else {
+            (None, None)
+        };
+        let worker = self.clone();
         tokio::spawn(async move {
-            Self::loop_scan().await;
+            worker.loop_scan(from_blocknumber, to_blocknumber).await;
         });
         Ok(())
     }
 
-    pub async fn loop_update_native_token_price() -> LoopResult {
-        let price = uniswapv3::calc_pool_price(JSON_CONFIG.wrap_token_pool.parse::<Address>().unwrap(), None).await?;
-        *NATIVE_TOKEN_PRICE.write().unwrap() = price;
-        log::info!("Update native token price: {}", price);
+    pub async fn loop_update_native_token_price(self: Arc<Self>) -> LoopResult {
+        let pool = self.config.wrap_token_pool.parse::<Address>()?;
+        let wrap_token = self.config.wrap_token.parse::<Address>()?;
+        let (token0, token1) = uniswapv3::get_tokens(&self.get_web3_rpc_client(), pool).await?;
+        let quote_token = if token0 == wrap_token { token1 } else { token0 };
+
+        let (base, quote) = futures::try_join!(self.get_token_info(wrap_token), self.get_token_info(quote_token))?;
+        let price = uniswapv3::calc_pool_price(
+            &self.get_web3_rpc_client(),
+            pool,
+            (wrap_token, base.decimals),
+            (quote_token, quote.decimals),
+            None,
+        )
+        .await?;
+        *self.native_token_price.write().unwrap() = price;
+        log::info!("[{}] Update native token price: {}", self.config.name, price);
         Ok(())
     }
 
-    pub async fn loop_scan() {
-        let web3 = get_web3_rpc_client();
-        let mut work_blocknumber = web3.get_blocknumber_wait().await;
+    pub async fn loop_scan(&self, from_blocknumber: Option<u64>, to_blocknumber: Option<u64>) {
+        let web3 = self.get_web3_rpc_client();
+        // A bounded backfill of an old range must not move the live scan cursor
+        let save_cursor = to_blocknumber.is_none();
+        let mut work_blocknumber = match from_blocknumber {
+            Some(blocknumber) => self.get_backfill_start_blocknumber(blocknumber).await,
+            None => self.get_start_blocknumber().await,
+        };
         loop {
-            let current_blocknumber = web3.get_blocknumber_wait().await - 1;
+            if to_blocknumber.is_some_and(|to_blocknumber| work_blocknumber > to_blocknumber) {
+                log::info!("[{}] Backfill finished at block: {}", self.config.name, work_blocknumber - 1);
+                return;
+            }
+
+            let mut current_blocknumber = web3.get_blocknumber_wait().await - 1;
+            if let Some(to_blocknumber) = to_blocknumber {
+                current_blocknumber = current_blocknumber.min(to_blocknumber);
+            }
             if work_blocknumber > current_blocknumber {
                 tokio::time::sleep(std::time::Duration::from_secs(1)).await;
                 continue;
             }
 
-            log::info!("Scann block: {}", work_blocknumber);
-            let block_id: BlockId = BlockId::Number(work_blocknumber.into());
-            match Self::yield_scan(block_id).await {
-                Ok(_) => {
-                    work_blocknumber += 1;
+            let end_blocknumber = current_blocknumber.min(work_blocknumber + SCAN_BATCH_SIZE - 1);
+            work_blocknumber = self.scan_range(work_blocknumber, end_blocknumber, save_cursor).await;
+        }
+    }
+
+    /// Fetch blocks concurrently but apply them strictly in order, so the minute buckets in
+    /// the volume cache are appended the same way as in live scanning. Returns the next block to scan.
+    pub async fn scan_range(&self, from_blocknumber: u64, to_blocknumber: u64, save_cursor: bool) -> u64 {
+        let mut blocks = futures::stream::iter(from_blocknumber..=to_blocknumber)
+            .map(|blocknumber| self.fetch_block(BlockId::Number(blocknumber.into())))
+            .buffered(*SCAN_CONCURRENCY);
+
+        let mut work_blocknumber = from_blocknumber;
+        while let Some(result) = blocks.next().await {
+            log::info!("[{}] Scann block: {}", self.config.name, work_blocknumber);
+            let result = match result {
+                Ok((block, _)) if self.is_reorg(&block) => match self.rollback_reorg(save_cursor).await {
+                    Ok(blocknumber) => return blocknumber,
+                    Err(e) => {
+                        log::error!("[{}] Error rolling back reorg at block {}: {}", self.config.name, work_blocknumber, e);
+                        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                        // Go on from what is left of the tracked chain, so the next pass sees the reorg again and keeps rolling back
+                        let tip = self.recent_blocks.read().unwrap().back().map(|tip| tip.blocknumber + 1);
+                        return tip.unwrap_or(work_blocknumber);
+                    }
+                },
+                Ok((block, block_receipts)) => self.commit_block(&block, block_receipts, save_cursor).await,
+                Err(e) => Err(e),
+            };
+
+            if let Err(e) = result {
+                log::error!("[{}] Error scanning block {}: {}", self.config.name, work_blocknumber, e);
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                break;
+            }
+            work_blocknumber += 1;
+        }
+
+        work_blocknumber
+    }
+
+    pub async fn yield_scan(&self, blocknumber: BlockId) -> anyhow::Result<()> {
+        let (block, block_receipts) = self.fetch_block(blocknumber).await?;
+        self.commit_block(&block, block_receipts, true).await
+    }
+
+    /// Apply a block to the caches and store it with the scan cursor. Nothing after the store can fail,
+    /// so a block retried after an error is never counted twice.
+    pub async fn commit_block(&self, block: &Block<H256>, block_receipts: Vec<TransactionReceipt>, save_cursor: bool) -> anyhow::Result<()> {
+        let blocknumber = block.number.ok_or_else(|| anyhow::anyhow!("Block number not found"))?.as_u64();
+        let blockhash = block.hash.ok_or_else(|| anyhow::anyhow!("Block hash not found"))?;
+
+        let mut events = self.apply_block(block, block_receipts).await?;
+        events.cursor = save_cursor.then(|| ScanCursorModel {
+            blocknumber,
+            blockhash: blockhash.to_hex_string(),
+            timestamp: block.timestamp.as_u64(),
+        });
+        if let Err(e) = self.db_save_events(&events).await {
+            self.rollback_volumes(&events.volumes);
+            self.restore_volatility(&events.volatility);
+            return Err(e);
+        }
+        self.apply_liquidity_events(&events.liquidity_events);
+
+        let timestamp = block.timestamp.as_u64();
+        self.head_timestamp.store(timestamp, Ordering::Relaxed);
+        self.volume_since
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |since| {
+                (since == 0 || timestamp < since).then_some(timestamp)
+            })
+            .ok();
+        {
+            let mut recent_blocks = self.recent_blocks.write().unwrap();
+            recent_blocks.push_back(RecentBlock {
+                blocknumber,
+                blockhash,
+                timestamp: block.timestamp.as_u64(),
+                volumes: events.volumes,
+                volatility: events.volatility,
+            });
+            if recent_blocks.len() > REORG_TRACK_BLOCKS {
+                recent_blocks.pop_front();
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_reorg(&self, block: &Block<H256>) -> bool {
+        let mut recent_blocks = self.recent_blocks.write().unwrap();
+        let Some(tip) = recent_blocks.back() else {
+            return false;
+        };
+
+        if block.number.is_none_or(|blocknumber| blocknumber.as_u64() != tip.blocknumber + 1) {
+            // Not a continuation of the tracked chain (e.g. a manual backfill range), start tracking over
+            recent_blocks.clear();
+            return false;
+        }
+
+        if tip.blockhash != block.parent_hash {
+            log::warn!(
+                "[{}] Reorg detected at block {}: parent {} != tracked {}",
+                self.config.name,
+                tip.blocknumber + 1,
+                block.parent_hash.to_hex_string(),
+                tip.blockhash.to_hex_string()
+            );
+            return true;
+        }
+        false
+    }
+
+    /// Walk back the tracked blocks until one matches the canonical chain, undoing every orphaned block on the way.
+    /// Each block leaves the store before it leaves the caches and the tracker, so an error part way can be resumed.
+    /// Returns the next block to scan.
+    pub async fn rollback_reorg(&self, save_cursor: bool) -> anyhow::Result<u64> {
+        let web3 = self.get_web3_rpc_client();
+        loop {
+            let (tip, parent) = {
+                let recent_blocks = self.recent_blocks.read().unwrap();
+                let Some(tip) = recent_blocks.back().cloned() else {
+                    return Err(anyhow::anyhow!("No tracked blocks to roll back"));
+                };
+                (tip, recent_blocks.iter().rev().nth(1).cloned())
+            };
+
+            let canonical = web3.eth().block(BlockId::Number(tip.blocknumber.into())).await?;
+            let canonical = canonical.ok_or_else(|| anyhow::anyhow!("Block not found"))?;
+            if canonical.hash == Some(tip.blockhash) {
+                log::warn!("[{}] Reorg rollback finished, common ancestor: {}", self.config.name, tip.blocknumber);
+                return Ok(tip.blocknumber + 1);
+            }
+
+            log::warn!(
+                "[{}] Rollback orphaned block {} {} with {} swaps",
+                self.config.name,
+                tip.blocknumber,
+                tip.blockhash.to_hex_string(),
+                tip.volumes.len()
+            );
+            self.db_delete_events_after(tip.blocknumber.saturating_sub(1)).await?;
+            if save_cursor && let Some(parent) = &parent {
+                self.db_save_cursor(parent.blocknumber, parent.blockhash, parent.timestamp).await?;
+            }
+            self.rollback_volumes(&tip.volumes);
+            self.restore_volatility(&tip.volatility);
+            self.recent_blocks.write().unwrap().pop_back();
+
+            if parent.is_none() {
+                // The cursor stays on the orphaned block until the next commit replaces it
+                log::error!(
+                    "[{}] Reorg deeper than {} tracked blocks, rescan from block {}",
+                    self.config.name,
+                    REORG_TRACK_BLOCKS,
+                    tip.blocknumber
+                );
+                return Ok(tip.blocknumber);
+            }
+        }
+    }
+
+    /// Put back the volatility of the pools a block added prices to
+    pub fn restore_volatility(&self, snapshots: &[VolatilitySnapshot]) {
+        let mut volatility = self.volatility.write().unwrap();
+        for (pool, snapshot) in snapshots {
+            match snapshot {
+                Some(snapshot) => volatility.insert(pool.clone(), snapshot.clone()),
+                None => volatility.remove(pool),
+            };
+        }
+    }
+
+    /// Add the closing prices of a block's V3 swaps, returning the state of every touched pool before them
+    fn add_volatility_prices(&self, swaps: &[SwapEventModel]) -> Vec<VolatilitySnapshot> {
+        let mut snapshots: Vec<VolatilitySnapshot> = Vec::new();
+        let mut volatility = self.volatility.write().unwrap();
+        for swap in swaps {
+            let Some(sqrt_price_x96) = swap.sqrt_price_x96.as_ref().and_then(|sqrt_price_x96| U256::from_dec_str(sqrt_price_x96).ok()) else {
+                continue;
+            };
+            let ln_price = 2.0 * web3_u256_to_f64(sqrt_price_x96).ln();
+            if !ln_price.is_finite() {
+                continue;
+            }
+
+            if !snapshots.iter().any(|(pool, _)| *pool == swap.pool) {
+                snapshots.push((swap.pool.clone(), volatility.get(&swap.pool).cloned()));
+            }
+            volatility.entry(swap.pool.clone()).or_default().add_price(swap.timestamp / 60, ln_price);
+        }
+        snapshots
+    }
+
+    pub fn rollback_volumes(&self, volumes: &[VolumeRecord]) {
+        {
+            let mut volume_cache = self.volume_cache.write().unwrap();
+            for record in volumes {
+                Self::remove_cached_volume(&mut volume_cache, &record.pool, record.ts_min, record.amount);
+            }
+        }
+        {
+            let mut category_volume_cache = self.category_volume_cache.write().unwrap();
+            for record in volumes {
+                let Some(category_volume) = category_volume_cache.get_mut(&record.pool) else {
+                    continue;
+                };
+
+                if let Some(pool_volume) = category_volume.get_mut(&record.category) {
+                    pool_volume.remove(record.ts_min, record.amount);
+                    if pool_volume.is_empty() {
+                        category_volume.remove(&record.category);
+                    }
                 }
-                Err(e) => {
-                    log::error!("Error scanning block {}: {}", work_blocknumber, e);
-                    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                if category_volume.is_empty() {
+                    category_volume_cache.remove(&record.pool);
                 }
             }
         }
+        let mut actors = self.actors.write().unwrap();
+        for record in volumes {
+            if let Some(pool_actors) = actors.get_mut(&record.pool) {
+                pool_actors.remove(record.ts_min, &record.actor, record.amount, record.is_token0_in, record.category);
+            }
+        }
     }
 
-    pub async fn yield_scan(blocknumber: BlockId) -> anyhow::Result<()> {
-        let web3 = get_web3_rpc_client();
-        let block = web3.eth().block(blocknumber).await?;
-        let block_receipts = web3.get_block_receiepts(blocknumber).await?;
+    fn remove_cached_volume(volume_cache: &mut VolumeCache, pool: &str, ts_min: u64, amount: u64) {
+        let Some(pool_volume) = volume_cache.get_mut(pool) else {
+            return;
+        };
+
+        pool_volume.remove(ts_min, amount);
+        if pool_volume.is_empty() {
+            volume_cache.remove(pool);
+        }
+    }
+
+    /// Add the classified swaps of a block to the category and actor caches, the total volume is added while parsing
+    fn add_flow_volumes(&self, volumes: &[VolumeRecord]) {
+        {
+            let mut category_volume_cache = self.category_volume_cache.write().unwrap();
+            for record in volumes {
+                category_volume_cache
+                    .entry(record.pool.clone())
+                    .or_default()
+                    .entry(record.category)
+                    .or_default()
+                    .add(record.ts_min, record.amount);
+            }
+        }
+        let mut actors = self.actors.write().unwrap();
+        for record in volumes {
+            actors
+                .entry(record.pool.clone())
+                .or_default()
+                .add(record.ts_min, &record.actor, record.amount, record.is_token0_in, record.category);
+        }
+    }
+
+    pub async fn fetch_block(&self, blocknumber: BlockId) -> anyhow::Result<(Block<H256>, Vec<TransactionReceipt>)> {
+        let web3 = self.get_web3_rpc_client();
+        let (block, block_receipts) = futures::try_join!(web3.eth().block(blocknumber), web3.get_block_receiepts(blocknumber))?;
 
         let block = block.ok_or_else(|| anyhow::anyhow!("Block not found"))?;
+        Ok((block, block_receipts))
+    }
+
+    pub async fn apply_block(&self, block: &Block<H256>, block_receipts: Vec<TransactionReceipt>) -> anyhow::Result<BlockEvents> {
+        let mut swaps = Vec::new();
+        let mut liquidity_events = Vec::new();
+        let mut created_pools = Vec::new();
         for receipt in block_receipts {
             // log::info!("tx: {}", receipt.transaction_hash.to_hex_string());
+            let tx_from = receipt.from.to_hex_string();
             for log in receipt.logs {
-                Self::parse_tx_log_v3_swap(&block, &log).await?;
+                // Registered right away, the new pool's first Mint is usually in the same tx
+                if let Some(created_pool) = self.parse_tx_log_pool_created(&log, block.timestamp.as_u64()) {
+                    self.register_created_pool(&created_pool);
+                    created_pools.push(created_pool);
+                    continue;
+                }
+
+                if let Some(liquidity_event) = self.parse_tx_log_v3_liquidity(block, &log) {
+                    liquidity_events.push(liquidity_event);
+                    continue;
+                }
+
+                match self.parse_tx_log_v3_swap(block, &log).await {
+                    Ok(Some(mut swap)) => {
+                        swap.tx_from = tx_from.clone();
+                        swaps.push(swap);
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        // The block will be retried, drop what it has added so far
+                        let mut volume_cache = self.volume_cache.write().unwrap();
+                        for swap in swaps.iter() {
+                            Self::remove_cached_volume(&mut volume_cache, &swap.pool, swap.timestamp / 60, swap.usd_value as u64);
+                        }
+                        return Err(e);
+                    }
+                }
             }
         }
 
+        let (pool_tokens, actors): (HashMap<String, (String, String)>, Vec<String>) = {
+            let pools = self.pools.read().unwrap();
+            let pool_tokens = swaps
+                .iter()
+                .filter_map(|swap| {
+                    pools
+                        .get(&swap.pool)
+                        .map(|info| (swap.pool.clone(), (info.token0.clone(), info.token1.clone())))
+                })
+                .collect();
+            let actors = swaps
+                .iter()
+                .map(|swap| swap_filter::get_swap_actor(swap, |address| pools.contains_key(address)).to_string())
+                .collect();
+            (pool_tokens, actors)
+        };
+        let actor_refs: Vec<&str> = actors.iter().map(String::as_str).collect();
+        let categories = swap_filter::classify_block_swaps(&swaps, &actor_refs, &pool_tokens);
+        let volumes: Vec<VolumeRecord> = swaps
+            .iter_mut()
+            .zip(actors.iter().zip(categories))
+            .map(|(swap, (actor, category))| {
+                swap.category = category.as_str().to_string();
+                VolumeRecord {
+                    pool: swap.pool.clone(),
+                    ts_min: swap.timestamp / 60,
+                    amount: swap.usd_value as u64,
+                    actor: actor.clone(),
+                    is_token0_in: swap_filter::is_token0_in(swap),
+                    category,
+                }
+            })
+            .collect();
+        self.add_flow_volumes(&volumes);
+        let volatility = self.add_volatility_prices(&swaps);
+
+        if !*PERSIST_SWAPS {
+            swaps.clear();
+        }
+        Ok(BlockEvents {
+            volumes,
+            volatility,
+            swaps,
+            liquidity_events,
+            created_pools,
+            cursor: None,
+        })
+    }
+
+    pub async fn db_save_events(&self, events: &BlockEvents) -> anyhow::Result<()> {
+        if events.swaps.is_empty() && events.liquidity_events.is_empty() && events.created_pools.is_empty() && events.cursor.is_none() {
+            return Ok(());
+        }
+
+        let mut tx = get_sqlite_pool().begin().await?;
+        if let Some(cursor) = &events.cursor {
+            sqlx::query("INSERT OR REPLACE INTO scan_cursor (chain_id, blocknumber, blockhash, timestamp) VALUES (?, ?, ?, ?)")
+                .bind(self.config.chain_id as i64)
+                .bind(cursor.blocknumber as i64)
+                .bind(&cursor.blockhash)
+                .bind(cursor.timestamp as i64)
+                .execute(&mut *tx)
+                .await?;
+        }
+        for swap in events.swaps.iter() {
+            sqlx::query(
+                "INSERT OR REPLACE INTO swaps (chain_id, blocknumber, tx_hash, log_index, pool, amount0, amount1, sqrt_price_x96, tick, liquidity, usd_value, sender, recipient, timestamp, tx_from, category) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(self.config.chain_id as i64)
+            .bind(swap.blocknumber as i64)
+            .bind(&swap.tx_hash)
+            .bind(swap.log_index as i64)
+            .bind(&swap.pool)
+            .bind(&swap.amount0)
+            .bind(&swap.amount1)
+            .bind(&swap.sqrt_price_x96)
+            .bind(swap.tick)
+            .bind(&swap.liquidity)
+            .bind(swap.usd_value)
+            .bind(&swap.sender)
+            .bind(&swap.recipient)
+            .bind(swap.timestamp as i64)
+            .bind(&swap.tx_from)
+            .bind(&swap.category)
+            .execute(&mut *tx)
+            .await?;
+        }
+        for event in events.liquidity_events.iter() {
+            sqlx::query(
+                "INSERT OR REPLACE INTO liquidity_events (chain_id, blocknumber, tx_hash, log_index, pool, event, owner, tick_lower, tick_upper, liquidity, amount0, amount1, usd_value, timestamp) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(self.config.chain_id as i64)
+            .bind(event.blocknumber as i64)
+            .bind(&event.tx_hash)
+            .bind(event.log_index as i64)
+            .bind(&event.pool)
+            .bind(&event.event)
+            .bind(&event.owner)
+            .bind(event.tick_lower)
+            .bind(event.tick_upper)
+            .bind(&event.liquidity)
+            .bind(&event.amount0)
+            .bind(&event.amount1)
+            .bind(event.usd_value)
+            .bind(event.timestamp as i64)
+            .execute(&mut *tx)
+            .await?;
+        }
+        for created_pool in events.created_pools.iter() {
+            sqlx::query(
+                "INSERT OR IGNORE INTO pool_created (chain_id, protocol, factory, pool, token0, token1, fee, tick_spacing, blocknumber, tx_hash, timestamp) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(self.config.chain_id as i64)
+            .bind(&created_pool.protocol)
+            .bind(&created_pool.factory)
+            .bind(&created_pool.pool)
+            .bind(&created_pool.token0)
+            .bind(&created_pool.token1)
+            .bind(created_pool.fee as i64)
+            .bind(created_pool.tick_spacing)
+            .bind(created_pool.blocknumber as i64)
+            .bind(&created_pool.tx_hash)
+            .bind(created_pool.timestamp as i64)
+            .execute(&mut *tx)
+            .await?;
+            // Zero liquidity and timestamp, the pool's first swap refreshes it
+            sqlx::query(
+                "INSERT OR IGNORE INTO pools (chain_id, protocol, pool, factory, token0, token1, fee, token0_liquidity, token1_liquidity, timestamp) VALUES (?, ?, ?, ?, ?, ?, ?, 0, 0, 0)",
+            )
+            .bind(self.config.chain_id as i64)
+            .bind(&created_pool.protocol)
+            .bind(&created_pool.pool)
+            .bind(&created_pool.factory)
+            .bind(&created_pool.token0)
+            .bind(&created_pool.token1)
+            .bind(created_pool.fee as i32)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
         Ok(())
     }
 
-    pub async fn get_pool_info(pool_protocol: &str, pool: Address) -> anyhow::Result<Option<PoolInfoModel>> {
+    pub async fn db_delete_events_after(&self, blocknumber: u64) -> anyhow::Result<()> {
+        for table in ["swaps", "liquidity_events", "pool_created"] {
+            sqlx::query(&format!("DELETE FROM {table} WHERE chain_id = ? AND blocknumber > ?"))
+                .bind(self.config.chain_id as i64)
+                .bind(blocknumber as i64)
+                .execute(get_sqlite_pool().as_ref())
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn get_liquidity_events(&self, pool: Address, limit: usize) -> anyhow::Result<Vec<LiquidityEventModel>> {
+        let events = sqlx::query_as::<_, LiquidityEventModel>(
+            "SELECT * FROM liquidity_events WHERE chain_id = ? AND pool = ? ORDER BY blocknumber DESC, log_index DESC LIMIT ?",
+        )
+        .bind(self.config.chain_id as i64)
+        .bind(pool.to_hex_string())
+        .bind(limit as i64)
+        .fetch_all(get_sqlite_pool().as_ref())
+        .await?;
+        Ok(events)
+    }
+
+    /// The most recently created pools of the configured factories
+    pub async fn get_created_pools(&self, limit: usize) -> anyhow::Result<Vec<PoolCreatedModel>> {
+        let pools = sqlx::query_as::<_, PoolCreatedModel>("SELECT * FROM pool_created WHERE chain_id = ? ORDER BY blocknumber DESC LIMIT ?")
+            .bind(self.config.chain_id as i64)
+            .bind(limit as i64)
+            .fetch_all(get_sqlite_pool().as_ref())
+            .await?;
+        Ok(pools)
+    }
+
+    pub fn parse_tx_log_pool_created(&self, tx_log: &Log, timestamp: u64) -> Option<PoolCreatedModel> {
+        let factory = tx_log.address.to_hex_string();
+        let protocol = self.config.factories.get(&factory)?.protocol.clone();
+        let created = uniswapv3::parse_pool_created_log(tx_log)?;
+        Some(PoolCreatedModel {
+            protocol,
+            factory,
+            pool: created.pool.to_hex_string(),
+            token0: created.token0.to_hex_string(),
+            token1: created.token1.to_hex_string(),
+            fee: created.fee,
+            tick_spacing: created.tick_spacing,
+            blocknumber: tx_log.block_number.map(|blocknumber| blocknumber.as_u64()).unwrap_or_default(),
+            tx_hash: tx_log.transaction_hash.unwrap_or_default().to_hex_string(),
+            timestamp,
+        })
+    }
+
+    /// Add a created pool to the registry, its liquidity is loaded by `get_pool_info` once it trades
+    pub fn register_created_pool(&self, created_pool: &PoolCreatedModel) {
+        let mut pools = self.pools.write().unwrap();
+        if pools.contains_key(&created_pool.pool) {
+            return;
+        }
+
+        log::info!(
+            "[{}] New pool: {} {} {}/{} fee: {}",
+            self.config.name,
+            created_pool.protocol,
+            created_pool.pool,
+            created_pool.token0,
+            created_pool.token1,
+            created_pool.fee
+        );
+        pools.insert(
+            created_pool.pool.clone(),
+            PoolInfoModel {
+                protocol: created_pool.protocol.clone(),
+                factory: created_pool.factory.clone(),
+                pool: created_pool.pool.clone(),
+                token0: created_pool.token0.clone(),
+                token1: created_pool.token1.clone(),
+                fee: created_pool.fee,
+                ..Default::default()
+            },
+        );
+    }
+
+    /// Walk the PoolCreated logs of the configured V3 factories up to the current head, in `--factory-sweep-batch` ranges.
+    /// A range the node refuses is halved, nodes cap the blocks or logs of an eth_getLogs call.
+    pub async fn sweep_factories(&self) {
+        const MAX_RETRY_DELAY: u64 = 60;
+        let factories: Vec<Address> = self.config.factories.keys().filter_map(|factory| factory.parse().ok()).collect();
+        let head_blocknumber = self.get_web3_rpc_client().get_blocknumber_wait().await;
+        let max_batch = (*FACTORY_SWEEP_BATCH).max(1);
+        let mut batch = max_batch;
+        let mut retry_delay = 1;
+        let mut from_blocknumber = *FACTORY_SWEEP_FROM_BLOCK;
+        let mut total = 0;
+        while from_blocknumber <= head_blocknumber {
+            let to_blocknumber = head_blocknumber.min(from_blocknumber + batch - 1);
+            match self.sweep_factories_range(&factories, from_blocknumber, to_blocknumber).await {
+                Ok(count) => {
+                    total += count;
+                    log::info!("[{}] Factory sweep at block {}, {} pools found", self.config.name, to_blocknumber, total);
+                    from_blocknumber = to_blocknumber + 1;
+                    retry_delay = 1;
+                    batch = (batch * 2).min(max_batch);
+                }
+                Err(e) => {
+                    let is_range_error = e.downcast_ref::<web3::Error>().is_some_and(is_log_range_error);
+                    if is_range_error && batch > 1 {
+                        batch = (to_blocknumber - from_blocknumber).div_ceil(2).max(1);
+                        log::warn!(
+                            "[{}] Factory sweep {} - {} failed: {}, retrying with {} blocks",
+                            self.config.name,
+                            from_blocknumber,
+                            to_blocknumber,
+                            e,
+                            batch
+                        );
+                        continue;
+                    }
+
+                    log::warn!(
+                        "[{}] Factory sweep {} - {} failed: {}, retrying in {}s",
+                        self.config.name,
+                        from_blocknumber,
+                        to_blocknumber,
+                        e,
+                        retry_delay
+                    );
+                    tokio::time::sleep(std::time::Duration::from_secs(retry_delay)).await;
+                    retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+                }
+            }
+        }
+        log::info!("[{}] Factory sweep finished, {} pools found", self.config.name, total);
+    }
+
+    pub async fn sweep_factories_range(&self, factories: &[Address], from_blocknumber: u64, to_blocknumber: u64) -> anyhow::Result<usize> {
+        let filter = FilterBuilder::default()
+            .address(factories.to_vec())
+            .topics(Some(vec![uniswapv3::POOL_CREATED_TOPIC.parse::<H256>()?]), None, None, None)
+            .from_block(from_blocknumber.into())
+            .to_block(to_blocknumber.into())
+            .build();
+        let logs = self.get_web3_rpc_client().eth().logs(filter).await?;
+
+        // Logs carry no timestamp, fetch every block with a new pool once
+        let blocknumbers: HashSet<u64> = logs.iter().filter_map(|log| log.block_number).map(|blocknumber| blocknumber.as_u64()).collect();
+        let timestamps: Vec<anyhow::Result<(u64, u64)>> = futures::stream::iter(blocknumbers)
+            .map(|blocknumber| self.get_block_timestamp(blocknumber))
+            .buffer_unordered(*SCAN_CONCURRENCY)
+            .collect()
+            .await;
+        let timestamps = timestamps.into_iter().collect::<anyhow::Result<HashMap<u64, u64>>>()?;
+
+        let created_pools: Vec<PoolCreatedModel> = logs
+            .iter()
+            .filter_map(|log| {
+                let timestamp = timestamps.get(&log.block_number?.as_u64()).copied().unwrap_or_default();
+                self.parse_tx_log_pool_created(log, timestamp)
+            })
+            .collect();
+        for created_pool in created_pools.iter() {
+            self.register_created_pool(created_pool);
+        }
+
+        let count = created_pools.len();
+        self.db_save_events(&BlockEvents {
+            created_pools,
+            ..Default::default()
+        })
+        .await?;
+        Ok(count)
+    }
+
+    pub async fn get_block_timestamp(&self, blocknumber: u64) -> anyhow::Result<(u64, u64)> {
+        let block = self.get_web3_rpc_client().eth().block(BlockId::Number(blocknumber.into())).await?;
+        let block = block.ok_or_else(|| anyhow::anyhow!("Block not found"))?;
+        Ok((blocknumber, block.timestamp.as_u64()))
+    }
+
+    pub async fn loop_prune_events(self: Arc<Self>) -> LoopResult {
+        for (table, retention_days) in [("swaps", *SWAP_RETENTION_DAYS), ("liquidity_events", *LIQUIDITY_EVENT_RETENTION_DAYS)] {
+            if retention_days == 0 {
+                continue;
+            }
+
+            let min_timestamp = get_timestamp().saturating_sub(retention_days * 24 * 60 * 60);
+            let result = sqlx::query(&format!("DELETE FROM {table} WHERE chain_id = ? AND timestamp < ?"))
+                .bind(self.config.chain_id as i64)
+                .bind(min_timestamp as i64)
+                .execute(get_sqlite_pool().as_ref())
+                .await?;
+            log::info!(
+                "[{}] Pruned {} {} older than {} days",
+                self.config.name,
+                result.rows_affected(),
+                table,
+                retention_days
+            );
+        }
+        Ok(())
+    }
+
+    /// Decode a Mint, Burn or Collect log of a tracked V3 pool
+    pub fn parse_tx_log_v3_liquidity(&self, block: &Block<H256>, tx_log: &Log) -> Option<LiquidityEventModel> {
+        let topic = tx_log.topics.first()?.to_hex_string();
+        let event = uniswapv3::parse_liquidity_log(tx_log, &topic)?;
+        let info = self.get_cached_pool_info(&tx_log.address.to_hex_string())?;
+        if self.config.v2_factories.contains_key(&info.factory) {
+            return None;
+        }
+
+        let usd_value = info.get_token_prices(self).map_or(0.0, |(price0, price1)| {
+            web3_u256_to_f64(event.amount0) / 10f64.powi(self.get_token_decimals(&info.token0) as i32) * price0
+                + web3_u256_to_f64(event.amount1) / 10f64.powi(self.get_token_decimals(&info.token1) as i32) * price1
+        });
+        log::info!(
+            "[{}] Liquidity {} pool: {} ticks: {} - {} liquidity: {} USD: {:.2}",
+            self.config.name,
+            event.kind.as_str(),
+            info.pool,
+            event.tick_lower,
+            event.tick_upper,
+            event.liquidity,
+            usd_value
+        );
+
+        Some(LiquidityEventModel {
+            blocknumber: block.number.map(|blocknumber| blocknumber.as_u64()).unwrap_or_default(),
+            tx_hash: tx_log.transaction_hash.unwrap_or_default().to_hex_string(),
+            log_index: tx_log.log_index.map(|log_index| log_index.as_u64()).unwrap_or_default(),
+            pool: info.pool,
+            event: event.kind.as_str().to_string(),
+            owner: event.owner.to_hex_string(),
+            tick_lower: event.tick_lower,
+            tick_upper: event.tick_upper,
+            liquidity: event.liquidity.to_string(),
+            amount0: event.amount0.to_string(),
+            amount1: event.amount1.to_string(),
+            usd_value,
+            timestamp: block.timestamp.as_u64(),
+        })
+    }
+
+    /// Move the cached balances and active liquidity by the block's liquidity events, so they do not wait for the
+    /// next `balanceOf` refresh. Events up to the block of the last refresh are already in the balances and skipped.
+    /// A reorged block is not taken back, the next refresh corrects it.
+    pub fn apply_liquidity_events(&self, events: &[LiquidityEventModel]) {
+        let updates: Vec<(String, f64, f64, i128)> = events
+            .iter()
+            .filter_map(|event| {
+                let info = self.get_cached_pool_info(&event.pool)?;
+                if event.blocknumber <= info.refreshed_block {
+                    return None;
+                }
+
+                let amount0 = U256::from_dec_str(&event.amount0).map(web3_u256_to_f64).unwrap_or_default();
+                let amount1 = U256::from_dec_str(&event.amount1).map(web3_u256_to_f64).unwrap_or_default();
+                let amount0 = amount0 / 10f64.powi(self.get_token_decimals(&info.token0) as i32);
+                let amount1 = amount1 / 10f64.powi(self.get_token_decimals(&info.token1) as i32);
+                let liquidity = event.liquidity.parse::<i128>().unwrap_or_default();
+                let in_range = (event.tick_lower..event.tick_upper).contains(&info.tick);
+                // Burn only credits the owner, the tokens leave the pool with the Collect
+                let (amount0, amount1, liquidity) = match event.event.as_str() {
+                    "mint" => (amount0, amount1, liquidity),
+                    "burn" => (0.0, 0.0, -liquidity),
+                    _ => (-amount0, -amount1, 0),
+                };
+                Some((event.pool.clone(), amount0, amount1, if in_range { liquidity } else { 0 }))
+            })
+            .collect();
+
+        let mut pools = self.pools.write().unwrap();
+        for (pool, amount0, amount1, liquidity) in updates {
+            let Some(info) = pools.get_mut(&pool) else {
+                continue;
+            };
+
+            info.token0_liquidity = (info.token0_liquidity as f64 + amount0).max(0.0) as u64;
+            info.token1_liquidity = (info.token1_liquidity as f64 + amount1).max(0.0) as u64;
+            info.liquidity = info.liquidity.saturating_add_signed(liquidity);
+        }
+    }
+
+    pub async fn get_pool_info(&self, pool_protocol: &str, pool: Address) -> anyhow::Result<Option<PoolInfoModel>> {
         const LIQUIDITY_TIMEOUT: u64 = 5 * 60;
-        let mut pool_info = if let Some(pool_info) = POOLS.read().unwrap().get(&pool.to_hex_string()) {
+        let mut pool_info = if let Some(pool_info) = self.pools.read().unwrap().get(&pool.to_hex_string()) {
             if get_timestamp() - pool_info.timestamp < LIQUIDITY_TIMEOUT {
                 return Ok(Some(pool_info.clone()));
             }
             pool_info.clone()
         } else {
-            if let Some(pool_info) = Self::get_pool_info_web3(pool_protocol, pool).await? {
+            if self.rejected_pools.read().unwrap().contains(&pool.to_hex_string()) {
+                return Ok(None);
+            }
+            if let Some(pool_info) = self.get_pool_info_web3(pool_protocol, pool).await? {
                 pool_info
             } else {
                 return Ok(None);
             }
         };
 
-        if get_timestamp() - pool_info.timestamp > LIQUIDITY_TIMEOUT {
-            let web31 = get_web3_rpc_client();
-            let web32 = get_web3_rpc_client();
-            let (token0_liquidity, token1_liquidity) = futures::try_join!(
-                web31.get_erc20_balance(pool_info.token0.parse::<Address>()?, pool),
-                web32.get_erc20_balance(pool_info.token1.parse::<Address>()?, pool)
-            )?;
-
-            pool_info.token0_liquidity = (token0_liquidity.as_u128() / (10i128.pow(18) as u128)) as u64;
-            pool_info.token1_liquidity = (token1_liquidity.as_u128() / (10i128.pow(18) as u128)) as u64;
-            pool_info.timestamp = get_timestamp();
+        if get_timestamp() - pool_info.timestamp > LIQUIDITY_TIMEOUT && !self.refresh_pool_liquidity(&mut pool_info, pool).await? {
+            return Ok(None);
         }
 
-        POOLS.write().unwrap().insert(pool.to_hex_string(), pool_info.clone());
+        self.pools.write().unwrap().insert(pool.to_hex_string(), pool_info.clone());
         sqlx::query(
-            "INSERT OR REPLACE INTO pools (protocol, pool, factory, token0, token1, fee, token0_liquidity, token1_liquidity, timestamp) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            "INSERT OR REPLACE INTO pools (chain_id, protocol, pool, factory, token0, token1, fee, token0_liquidity, token1_liquidity, timestamp) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
         )
+        .bind(self.config.chain_id as i64)
         .bind(&pool_info.protocol)
         .bind(&pool_info.pool)
         .bind(&pool_info.factory)
@@ -172,123 +1550,215 @@ impl V3ScanWorker {
         Ok(Some(pool_info))
     }
 
-    pub async fn get_pool_info_web3(pool_protocol: &str, pool: Address) -> anyhow::Result<Option<PoolInfoModel>> {
-        let web31 = get_web3_rpc_client();
-        let web32 = get_web3_rpc_client();
-        let web33 = get_web3_rpc_client();
+    pub async fn get_pool_info_web3(&self, pool_protocol: &str, pool: Address) -> anyhow::Result<Option<PoolInfoModel>> {
+        let web31 = self.get_web3_rpc_client();
+        let web32 = self.get_web3_rpc_client();
+        let web33 = self.get_web3_rpc_client();
 
-        log::info!("Get pool info: {} {}", pool_protocol, pool.to_hex_string());
-        let factory = web31.query_smart_contract::<Address, _>(pool, UNISWAPV3_POOL_ABI, "factory", (), None).await?;
-        if !JSON_CONFIG.factories.contains_key(&factory.to_hex_string()) {
+        log::info!("[{}] Get pool info: {} {}", self.config.name, pool_protocol, pool.to_hex_string());
+        // Forks of every kind share the Swap topics, anything that can't name a configured factory is not ours
+        let factory = match web31.query_smart_contract::<Address, _>(pool, UNISWAPV3_POOL_ABI, "factory", (), None).await {
+            Ok(factory) => factory,
+            Err(e) => {
+                log::debug!("[{}] Skip pool {} without factory: {}", self.config.name, pool.to_hex_string(), e);
+                if !is_transport_error(&e) {
+                    self.reject_pool(pool);
+                }
+                return Ok(None);
+            }
+        };
+        let (pool_protocol, fee_rate, token0, token1) = if let Some(v2_factory) = self.config.v2_factories.get(&factory.to_hex_string()) {
+            let (token0, token1) = uniswapv2::get_tokens(&web32, pool).await?;
+            (v2_factory.protocol.clone(), v2_factory.fee, token0, token1)
+        } else if let Some(v3_factory) = self.config.factories.get(&factory.to_hex_string()) {
+            let (fee_rate, token0, token1) = futures::try_join!(
+                web31.query_smart_contract::<u64, _>(pool, UNISWAPV3_POOL_ABI, "fee", (), None),
+                web32.query_smart_contract::<Address, _>(pool, UNISWAPV3_POOL_ABI, "token0", (), None),
+                web33.query_smart_contract::<Address, _>(pool, UNISWAPV3_POOL_ABI, "token1", (), None)
+            )?;
+            // Forks share the V3 Swap topic, the factory tells them apart
+            (v3_factory.protocol.clone(), fee_rate, token0, token1)
+        } else {
+            self.reject_pool(pool);
             return Ok(None);
-        }
-
-        let (fee_rate, token0, token1) = futures::try_join!(
-            web31.query_smart_contract::<u64, _>(pool, UNISWAPV3_POOL_ABI, "fee", (), None),
-            web32.query_smart_contract::<Address, _>(pool, UNISWAPV3_POOL_ABI, "token0", (), None),
-            web33.query_smart_contract::<Address, _>(pool, UNISWAPV3_POOL_ABI, "token1", (), None)
-        )?;
+        };
 
         log::info!(
-            "Pool: {}, Token0: {}, Token1: {}, Fee: {}",
+            "[{}] Pool: {}, Token0: {}, Token1: {}, Fee: {}",
+            self.config.name,
             pool.to_hex_string(),
             token0.to_hex_string(),
             token1.to_hex_string(),
             fee_rate
         );
 
-        let divisor = U256::exp10(18);
-        let (token0_liquidity, token1_liquidity) = futures::try_join!(web31.get_erc20_balance(token0, pool), web32.get_erc20_balance(token1, pool))?;
-        let token0_liquidity = token0_liquidity / divisor;
-        let token1_liquidity = token1_liquidity / divisor;
-
-        let pool_info = PoolInfoModel {
-            protocol: pool_protocol.to_string(),
+        let mut pool_info = PoolInfoModel {
+            protocol: pool_protocol,
             factory: factory.to_hex_string(),
             pool: pool.to_hex_string(),
             token0: token0.to_hex_string(),
             token1: token1.to_hex_string(),
             fee: fee_rate,
-            token0_liquidity: token0_liquidity.as_u64(),
-            token1_liquidity: token1_liquidity.as_u64(),
+            token0_liquidity: 0,
+            token1_liquidity: 0,
             timestamp: get_timestamp(),
+            ..Default::default()
         };
 
+        if !self.refresh_pool_liquidity(&mut pool_info, pool).await? {
+            self.reject_pool(pool);
+            return Ok(None);
+        }
         Ok(Some(pool_info))
     }
 
+    fn reject_pool(&self, pool: Address) {
+        let mut rejected_pools = self.rejected_pools.write().unwrap();
+        if rejected_pools.len() >= MAX_REJECTED_POOLS {
+            rejected_pools.clear();
+        }
+        rejected_pools.insert(pool.to_hex_string());
+    }
+
+    /// Read the reserves and V3 state of the pool, false when the token decimals can't scale its reserves
+    pub async fn refresh_pool_liquidity(&self, pool_info: &mut PoolInfoModel, pool: Address) -> anyhow::Result<bool> {
+        let (token0, token1) = futures::try_join!(
+            self.get_token_info(pool_info.token0.parse::<Address>()?),
+            self.get_token_info(pool_info.token1.parse::<Address>()?)
+        )?;
+
+        // Everything is read at one block, so the liquidity events of the blocks up to it can be told apart
+        let blocknumber = self.get_web3_rpc_client().eth().block_number().await?.as_u64();
+        let block = Some(BlockId::Number(blocknumber.into()));
+        let (token0_liquidity, token1_liquidity) = self.get_pool_reserves(pool_info, pool, block).await?;
+        let (Some(unit0), Some(unit1)) = (
+            U256::from(10).checked_pow(U256::from(token0.decimals)),
+            U256::from(10).checked_pow(U256::from(token1.decimals)),
+        ) else {
+            log::warn!(
+                "[{}] Skip pool {} with token decimals {} and {}",
+                self.config.name,
+                pool.to_hex_string(),
+                token0.decimals,
+                token1.decimals
+            );
+            return Ok(false);
+        };
+        pool_info.token0_liquidity = u64::try_from(token0_liquidity / unit0).unwrap_or(u64::MAX);
+        pool_info.token1_liquidity = u64::try_from(token1_liquidity / unit1).unwrap_or(u64::MAX);
+
+        if self.config.v2_factories.contains_key(&pool_info.factory) {
+            pool_info.v2_price = uniswapv3::calc_token_price(
+                web3_u256_to_f64(token1_liquidity) / web3_u256_to_f64(token0_liquidity),
+                true,
+                token0.decimals,
+                token1.decimals,
+            );
+        } else {
+            let state = uniswapv3::get_pool_state(&self.get_web3_rpc_client(), pool, block).await?;
+            pool_info.sqrt_price = web3_u256_to_f64(state.sqrt_price_x96) / 2.0f64.powi(96);
+            pool_info.tick = state.tick;
+            pool_info.liquidity = state.liquidity;
+            if let Some(factory) = self.config.factories.get(&pool_info.factory) {
+                pool_info.protocol_fee_share = Some(uniswapv3::calc_protocol_fee_share(factory.fee_protocol_layout, state.fee_protocol));
+            }
+        }
+
+        pool_info.refreshed_block = blocknumber;
+        pool_info.timestamp = get_timestamp();
+        Ok(true)
+    }
+
+    pub async fn get_pool_reserves(&self, pool_info: &PoolInfoModel, pool: Address, block: Option<BlockId>) -> anyhow::Result<(U256, U256)> {
+        if self.config.v2_factories.contains_key(&pool_info.factory) {
+            return Ok(uniswapv2::get_reserves(&self.get_web3_rpc_client(), pool, block).await?);
+        }
+
+        let web31 = self.get_web3_rpc_client();
+        let web32 = self.get_web3_rpc_client();
+        let reserves = futures::try_join!(
+            web31.query_smart_contract::<U256, _>(pool_info.token0.parse::<Address>()?, ERC20_ABI, "balanceOf", (pool,), block),
+            web32.query_smart_contract::<U256, _>(pool_info.token1.parse::<Address>()?, ERC20_ABI, "balanceOf", (pool,), block)
+        )?;
+        Ok(reserves)
+    }
+
     pub fn parse_tx_log_v3_swap_amount(log: &web3::types::Log) -> (i128, i128) {
         let data = log.data.0.as_slice();
-        let amount0 = U256::from_big_endian(&data[32 * 0..32 * 1]);
-        let amount1 = U256::from_big_endian(&data[32 * 1..32 * 2]);
+        let amount0 = U256::from_big_endian(&data[0..32]);
+        let amount1 = U256::from_big_endian(&data[32..64]);
 
         (web3_u256_to_i128(amount0), web3_u256_to_i128(amount1))
     }
 
-    pub async fn parse_tx_log_v3_swap(block: &Block<H256>, tx_log: &Log) -> anyhow::Result<()> {
-        if tx_log.topics.len() == 0 {
-            return Ok(());
+    /// sqrtPriceX96, liquidity and tick of the pool after a V3 swap
+    pub fn parse_tx_log_v3_swap_state(log: &web3::types::Log) -> (U256, u128, i32) {
+        let data = log.data.0.as_slice();
+        let sqrt_price_x96 = U256::from_big_endian(&data[64..96]);
+        let liquidity = U256::from_big_endian(&data[96..128]).low_u128();
+        let tick = web3_u256_to_i128(U256::from_big_endian(&data[128..160])) as i32;
+
+        (sqrt_price_x96, liquidity, tick)
+    }
+
+    pub async fn parse_tx_log_v3_swap(&self, block: &Block<H256>, tx_log: &Log) -> anyhow::Result<Option<SwapEventModel>> {
+        if tx_log.topics.len() < 3 {
+            return Ok(None);
         }
 
         let topic = tx_log.topics[0].to_hex_string();
-        let pool_protocol = if let Some(protocol) = JSON_CONFIG.swap_topics.get(&topic) {
+        let pool_protocol = if let Some(protocol) = self.config.swap_topics.get(&topic) {
             protocol
         } else {
-            return Ok(());
+            return Ok(None);
         };
 
-        let Some(pool_info) = Self::get_pool_info(pool_protocol, tx_log.address).await? else {
-            return Ok(());
+        let Some(pool_info) = self.get_pool_info(pool_protocol, tx_log.address).await? else {
+            return Ok(None);
         };
 
-        let (amount0, amount1) = Self::parse_tx_log_v3_swap_amount(tx_log);
-        let (token, liquidity, amount) = if pool_info.token0 == JSON_CONFIG.wrap_token || JSON_CONFIG.stable_tokens.contains_key(&pool_info.token0) {
-            (pool_info.token0, pool_info.token0_liquidity as f64, amount0.abs() as f64)
-        } else if pool_info.token1 == JSON_CONFIG.wrap_token || JSON_CONFIG.stable_tokens.contains_key(&pool_info.token1) {
-            (pool_info.token1, pool_info.token1_liquidity as f64, amount1.abs() as f64)
+        let amounts = if topic == uniswapv2::SWAP_TOPIC {
+            uniswapv2::parse_swap_amount(tx_log)
         } else {
-            return Ok(());
+            Some(Self::parse_tx_log_v3_swap_amount(tx_log))
+        };
+        let Some((amount0, amount1)) = amounts else {
+            return Ok(None);
+        };
+        let Some((is_token0, price)) = pool_info.get_quote_token(self) else {
+            return Ok(None);
+        };
+        let (token, liquidity, amount) = if is_token0 {
+            (pool_info.token0.clone(), pool_info.token0_liquidity as f64, amount0.abs() as f64)
+        } else {
+            (pool_info.token1.clone(), pool_info.token1_liquidity as f64, amount1.abs() as f64)
         };
 
+        // Dust pools hold less than 1000 whole quote tokens
         if liquidity < 1000.0 {
-            return Ok(());
+            return Ok(None);
         }
+        let liquidity = liquidity * price;
 
-        let (amount, liquidity) = if token == JSON_CONFIG.wrap_token {
-            (amount * *NATIVE_TOKEN_PRICE.read().unwrap(), liquidity * *NATIVE_TOKEN_PRICE.read().unwrap())
-        } else {
-            (amount, liquidity)
-        };
-
-        let amount = amount / (10i128.pow(18) as f64);
+        let amount = amount * price / 10f64.powi(self.get_token_decimals(&token) as i32);
         if !amount.is_normal() {
-            return Ok(());
+            return Ok(None);
         }
 
         let ts_min = block.timestamp.as_u64() / 60;
-        let mut volume_cache = VOLUME_CACHE.write().unwrap();
+        let mut volume_cache = self.volume_cache.write().unwrap();
         let pool_volume = volume_cache.entry(pool_info.pool.clone()).or_default();
-        if let Some((last_ts, last_amount)) = pool_volume.back_mut() {
-            if *last_ts == ts_min {
-                *last_amount += amount as u64;
-            } else {
-                pool_volume.push_back((ts_min, amount as u64));
-            }
-        } else {
-            pool_volume.push_back((ts_min, amount as u64));
-        }
-
-        if pool_volume.len() > VOLUME_MINUTES_CACHE_SIZE {
-            pool_volume.pop_front();
-        }
+        pool_volume.add(ts_min, amount as u64);
 
-        let total_volume: u64 = pool_volume.iter().map(|(_, amt)| *amt).sum();
-        let total_fee_cache = pool_info.fee * total_volume / 1000000;
-        let total_fee_hour = ((total_fee_cache as f64) / (VOLUME_MINUTES_CACHE_SIZE as f64)) * 60.0;
-        let fee_rate_per_hour = total_fee_hour as f64 / liquidity as f64;
+        let window = VolumeWindow::get_default();
+        let (_, since_min) = self.get_volume_clock();
+        let total_volume = pool_volume.get_volume(window, ts_min);
+        let hours = window.get_elapsed_hours(ts_min, since_min.min(ts_min));
+        let fee_rate_per_hour = Self::calc_fee_rate_per_hour(pool_info.fee, total_volume, liquidity, hours);
 
         log::info!(
-            "-{}s Pool: {}, Fee: {} Amount: {}, APH: {} TotalVolume: {} Liquidity: {}",
+            "[{}] -{}s Pool: {}, Fee: {} Amount: {}, APH: {} TotalVolume: {} Liquidity: {}",
+            self.config.name,
             get_timestamp() - block.timestamp.as_u64(),
             pool_info.pool,
             pool_info.fee,
@@ -300,57 +1770,316 @@ impl V3ScanWorker {
 
         if fee_rate_per_hour > 0.1 {
             log::info!(
-                "high yield pool: {} pool: {} token: {} APH: {} Liquidity: {}",
-                pool_protocol,
+                "[{}] high yield pool: {} pool: {} token: {} APH: {} Liquidity: {}",
+                self.config.name,
+                pool_info.protocol,
                 pool_info.pool,
                 token,
                 fee_rate_per_hour,
                 liquidity as u64
             );
         }
+
+        let (sqrt_price_x96, tick, liquidity) = if topic == uniswapv2::SWAP_TOPIC {
+            (None, None, None)
+        } else {
+            let (sqrt_price_x96, liquidity, tick) = Self::parse_tx_log_v3_swap_state(tx_log);
+            (Some(sqrt_price_x96.to_string()), Some(tick), Some(liquidity.to_string()))
+        };
+        Ok(Some(SwapEventModel {
+            blocknumber: block.number.map(|blocknumber| blocknumber.as_u64()).unwrap_or_default(),
+            tx_hash: tx_log.transaction_hash.unwrap_or_default().to_hex_string(),
+            log_index: tx_log.log_index.map(|log_index| log_index.as_u64()).unwrap_or_default(),
+            pool: pool_info.pool,
+            amount0: amount0.to_string(),
+            amount1: amount1.to_string(),
+            sqrt_price_x96,
+            tick,
+            liquidity,
+            usd_value: amount,
+            sender: Address::from(tx_log.topics[1]).to_hex_string(),
+            recipient: Address::from(tx_log.topics[2]).to_hex_string(),
+            timestamp: block.timestamp.as_u64(),
+            tx_from: String::new(),
+            category: SwapCategory::default().as_str().to_string(),
+        }))
+    }
+
+    pub fn calc_fee_rate_per_hour(fee: u64, total_volume: u64, liquidity: f64, hours: f64) -> f64 {
+        let total_fee = fee as f64 * total_volume as f64 / 1000000.0;
+        total_fee / hours / liquidity
+    }
+
+    /// The slot0 fee switch of V3 pools, otherwise the configured share of the protocol
+    pub fn get_protocol_fee_share(&self, info: &PoolInfoModel) -> f64 {
+        info.protocol_fee_share
+            .unwrap_or_else(|| self.config.protocol_fee_shares.get(&info.protocol).copied().unwrap_or(0.0))
+    }
+
+    pub fn calc_window_yield(&self, window: &VolumeWindow, info: &PoolInfoModel, volumes: &PoolVolume, liquidity: f64, active_liquidity: f64) -> WindowYield {
+        let (now_min, since_min) = self.get_volume_clock();
+        let volume = volumes.get_volume(window, now_min);
+        let hours = window.get_elapsed_hours(now_min, since_min);
+        let aph = Self::calc_fee_rate_per_hour(info.fee, volume, liquidity, hours);
+        let active_aph = Self::calc_fee_rate_per_hour(info.fee, volume, active_liquidity, hours);
+        let protocol_fee_share = self.get_protocol_fee_share(info);
+        let lp_share = 1.0 - protocol_fee_share;
+        let realized = self.calc_realized_fees_per_hour(info, window.minutes);
+        let volatility = self.get_realized_volatility(info, window, now_min, hours);
+        let mut category_volumes = self.get_category_volumes(&info.pool, window, now_min);
+        let (top_actor, circular_share) = match self.actors.read().unwrap().get(&info.pool) {
+            Some(actors) => (actors.get_top_actor(window, now_min), actors.get_circular_share(window, now_min)),
+            None => (None, 0.0),
+        };
+
+        // Round trips across blocks move from the retail volume to the circular volume
+        let retail_volume = category_volumes.get(&SwapCategory::Retail).copied().unwrap_or_default();
+        let circular_volume = retail_volume * circular_share;
+        if circular_volume > 0.0 {
+            category_volumes.insert(SwapCategory::Retail, retail_volume - circular_volume);
+            *category_volumes.entry(SwapCategory::Circular).or_default() += circular_volume;
+        }
+        let organic_volume = retail_volume - circular_volume;
+        WindowYield {
+            window: window.name.clone(),
+            hours,
+            volume: volume as f64,
+            aph,
+            active_aph,
+            net_aph: aph * lp_share,
+            net_active_aph: active_aph * lp_share,
+            realized_hours: realized.map(|(hours, _)| hours),
+            realized_aph: realized.map(|(_, fees_per_hour)| fees_per_hour / liquidity),
+            realized_active_aph: realized.map(|(_, fees_per_hour)| fees_per_hour / active_liquidity),
+            volatility: volatility.map(|volatility| volatility.volatility),
+            il_per_hour: volatility.map(|volatility| volatility.il_per_hour),
+            adjusted_aph: volatility.map(|volatility| aph * lp_share - volatility.il_per_hour),
+            organic_volume,
+            organic_aph: Self::calc_fee_rate_per_hour(info.fee, organic_volume as u64, liquidity, hours),
+            dominated: top_actor.as_ref().is_some_and(|(_, share)| *share > *MAX_ACTOR_SHARE),
+            top_actor_share: top_actor.as_ref().map(|(_, share)| *share),
+            top_actor: top_actor.map(|(actor, _)| actor),
+            category_volumes,
+            annual: AnnualYield::from_aph(aph, protocol_fee_share),
+        }
+    }
+
+    pub fn get_category_volumes(&self, pool: &str, window: &VolumeWindow, now_min: u64) -> BTreeMap<SwapCategory, f64> {
+        let category_volume_cache = self.category_volume_cache.read().unwrap();
+        let Some(category_volume) = category_volume_cache.get(pool) else {
+            return BTreeMap::new();
+        };
+        category_volume
+            .iter()
+            .map(|(category, pool_volume)| (*category, pool_volume.get_volume(window, now_min) as f64))
+            .collect()
+    }
+
+    pub fn get_realized_volatility(&self, info: &PoolInfoModel, window: &VolumeWindow, now_min: u64, hours: f64) -> Option<RealizedVolatility> {
+        let variance = self.volatility.read().unwrap().get(&info.pool)?.get_variance(window, now_min)?;
+        Some(RealizedVolatility::from_variance(variance, hours))
+    }
+
+    /// USD fees paid to the in-range liquidity per hour, from the feeGrowthGlobal samples within the last `minutes`.
+    /// Fee growth is per unit of liquidity, so it is scaled by the current liquidity of the pool.
+    pub fn calc_realized_fees_per_hour(&self, info: &PoolInfoModel, minutes: u64) -> Option<(f64, f64)> {
+        let realized = self.fee_growth.read().unwrap().get(&info.pool)?.get_realized(minutes)?;
+        let (price0, price1) = info.get_token_prices(self)?;
+        let liquidity = info.liquidity as f64;
+        let fees0 = realized.fee_growth0 * liquidity / 10f64.powi(self.get_token_decimals(&info.token0) as i32) * price0;
+        let fees1 = realized.fee_growth1 * liquidity / 10f64.powi(self.get_token_decimals(&info.token1) as i32) * price1;
+        Some((realized.hours, (fees0 + fees1) / realized.hours))
+    }
+
+    pub async fn sample_fee_growth(&self, pool: String) -> anyhow::Result<(String, FeeGrowthSample)> {
+        let (fee_growth0_x128, fee_growth1_x128) = uniswapv3::get_fee_growth(&self.get_web3_rpc_client(), pool.parse::<Address>()?, None).await?;
+        let sample = FeeGrowthSample {
+            timestamp: get_timestamp(),
+            fee_growth0_x128,
+            fee_growth1_x128,
+        };
+        Ok((pool, sample))
+    }
+
+    pub async fn loop_sample_fee_growth(self: Arc<Self>) -> LoopResult {
+        let pools: Vec<String> = {
+            let pools = self.pools.read().unwrap();
+            let volume_cache = self.volume_cache.read().unwrap();
+            volume_cache
+                .keys()
+                .filter(|pool| pools.get(*pool).is_some_and(|info| !self.config.v2_factories.contains_key(&info.factory)))
+                .cloned()
+                .collect()
+        };
+
+        let results: Vec<anyhow::Result<(String, FeeGrowthSample)>> = futures::stream::iter(pools.clone())
+            .map(|pool| self.sample_fee_growth(pool))
+            .buffer_unordered(*SCAN_CONCURRENCY)
+            .collect()
+            .await;
+        let samples: Vec<(String, FeeGrowthSample)> = results
+            .into_iter()
+            .filter_map(|result| result.map_err(|e| log::warn!("[{}] Failed to sample fee growth: {}", self.config.name, e)).ok())
+            .collect();
+
+        let mut fee_growth = self.fee_growth.write().unwrap();
+        fee_growth.retain(|pool, _| pools.contains(pool));
+        for (pool, sample) in samples.iter() {
+            fee_growth.entry(pool.clone()).or_default().push(*sample);
+        }
+        log::info!("[{}] Sampled fee growth of {}/{} pools", self.config.name, samples.len(), pools.len());
         Ok(())
     }
 
-    pub async fn loop_sort_yield() -> LoopResult {
-        let all_pool_info = POOLS.read().unwrap();
-        let all_pool_volume = VOLUME_CACHE.read().unwrap();
-        let mut pools: Vec<(String, f64, f64, f64)> = all_pool_volume
+    pub fn calc_window_yields(&self, info: &PoolInfoModel, volumes: &PoolVolume, liquidity: f64, active_liquidity: f64) -> Vec<WindowYield> {
+        VOLUME_WINDOWS
+            .iter()
+            .map(|window| self.calc_window_yield(window, info, volumes, liquidity, active_liquidity))
+            .collect()
+    }
+
+    pub fn get_top_pools(&self, filter: &YieldFilter) -> anyhow::Result<Vec<PoolYield>> {
+        let window = filter.get_window()?;
+        let all_pool_info = self.pools.read().unwrap();
+        let all_pool_volume = self.volume_cache.read().unwrap();
+        let mut pools: Vec<PoolYield> = all_pool_volume
             .iter()
             .filter_map(|(pool, volumes)| {
                 let Some(pool_info) = all_pool_info.get(pool) else {
-                    log::warn!("WPool {} not found in POOLS", pool);
+                    log::warn!("[{}] WPool {} not found in pools", self.config.name, pool);
                     return None;
                 };
 
-                let liquidity = pool_info.get_liquidity();
-                if liquidity < 10000.0 {
-                    log::warn!("WPool {} has low liquidity: {}", pool, liquidity);
+                if filter.protocol.as_ref().is_some_and(|protocol| *protocol != pool_info.protocol) {
                     return None;
                 }
 
-                let total_volume: u64 = volumes.iter().map(|(_, amt)| *amt).sum();
-                if total_volume < 10000 {
-                    log::warn!("WPool {} has low total volume: {}", pool, total_volume);
+                let liquidity = pool_info.get_liquidity(self);
+                if liquidity < filter.min_liquidity {
+                    log::warn!("WPool {} has low liquidity: {}", pool, liquidity);
                     return None;
                 }
 
-                let total_fee_cache = pool_info.fee * total_volume / 1000000;
-                let total_fee_hour = ((total_fee_cache as f64) / (VOLUME_MINUTES_CACHE_SIZE as f64)) * 60.0;
-                let fee_rate_per_hour = total_fee_hour as f64 / liquidity as f64;
+                let active_liquidity = pool_info.get_active_liquidity(self, *ACTIVE_TICK_RANGE);
+                let window_yield = self.calc_window_yield(window, pool_info, volumes, liquidity, active_liquidity);
+                if window_yield.volume < filter.min_volume {
+                    log::warn!("WPool {} has low total volume: {}", pool, window_yield.volume);
+                    return None;
+                }
 
-                Some((pool.clone(), total_volume as f64, liquidity, fee_rate_per_hour))
+                Some(PoolYield {
+                    pool: pool.clone(),
+                    protocol: pool_info.protocol.clone(),
+                    token0: pool_info.token0.clone(),
+                    token1: pool_info.token1.clone(),
+                    token0_symbol: self.get_token_symbol(&pool_info.token0),
+                    token1_symbol: self.get_token_symbol(&pool_info.token1),
+                    fee: pool_info.fee,
+                    protocol_fee_share: self.get_protocol_fee_share(pool_info),
+                    liquidity,
+                    active_liquidity,
+                    window_yield,
+                    windows: self.calc_window_yields(pool_info, volumes, liquidity, active_liquidity),
+                })
             })
             .collect();
-        pools.sort_by(|a, b| b.3.partial_cmp(&a.3).unwrap_or(std::cmp::Ordering::Equal));
-        pools.truncate(10);
-        log::info!("Top 10 pools by fee rate per hour:");
-        for (pool, total_volume, liquidity, fee_rate_per_hour) in pools {
+        match filter.sort {
+            YieldSort::Aph => pools.sort_by(|a, b| b.window_yield.aph.partial_cmp(&a.window_yield.aph).unwrap_or(std::cmp::Ordering::Equal)),
+            YieldSort::AdjustedAph => pools.sort_by(|a, b| {
+                let (a, b) = (a.window_yield.adjusted_aph.unwrap_or(f64::MIN), b.window_yield.adjusted_aph.unwrap_or(f64::MIN));
+                b.partial_cmp(&a).unwrap_or(std::cmp::Ordering::Equal)
+            }),
+            YieldSort::OrganicAph => pools.sort_by(|a, b| {
+                b.window_yield
+                    .organic_aph
+                    .partial_cmp(&a.window_yield.organic_aph)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            }),
+        }
+        pools.truncate(filter.limit);
+        Ok(pools)
+    }
+
+    /// Price of the non-quote token in the quote token (wrapped native or stable), or token0 in token1 otherwise
+    pub async fn get_pool_price(&self, info: &PoolInfoModel, pool: Address) -> anyhow::Result<f64> {
+        let token0 = info.token0.parse::<Address>()?;
+        let token1 = info.token1.parse::<Address>()?;
+        let base_is_token0 = !matches!(info.get_quote_token(self), Some((true, _)));
+        let (base, quote) = if base_is_token0 { (token0, token1) } else { (token1, token0) };
+        let base = (base, self.get_token_decimals(&base.to_hex_string()));
+        let quote = (quote, self.get_token_decimals(&quote.to_hex_string()));
+
+        if self.config.v2_factories.contains_key(&info.factory) {
+            let (reserve0, reserve1) = uniswapv2::get_reserves(&self.get_web3_rpc_client(), pool, None).await?;
+            let raw_price = web3_u256_to_f64(reserve1) / web3_u256_to_f64(reserve0);
+            return Ok(uniswapv3::calc_token_price(raw_price, base_is_token0, base.1, quote.1));
+        }
+
+        uniswapv3::calc_pool_price(&self.get_web3_rpc_client(), pool, base, quote, None).await
+    }
+
+    pub async fn get_pool_detail(&self, pool: Address) -> anyhow::Result<Option<PoolDetail>> {
+        let Some(info) = self.get_cached_pool_info(&pool.to_hex_string()) else {
+            return Ok(None);
+        };
+        let volumes = self.get_pool_volume(&info.pool);
+
+        let liquidity = info.get_liquidity(self);
+        let active_liquidity = info.get_active_liquidity(self, *ACTIVE_TICK_RANGE);
+        let window_yield = self.calc_window_yield(VolumeWindow::get_default(), &info, &volumes, liquidity, active_liquidity);
+        let windows = self.calc_window_yields(&info, &volumes, liquidity, active_liquidity);
+        let price = match self.get_pool_price(&info, pool).await {
+            Ok(price) => Some(price),
+            Err(e) => {
+                log::warn!("[{}] Failed to get pool price {}: {}", self.config.name, info.pool, e);
+                None
+            }
+        };
+
+        Ok(Some(PoolDetail {
+            liquidity_age: get_timestamp().saturating_sub(info.timestamp),
+            token0_symbol: self.get_token_symbol(&info.token0),
+            token1_symbol: self.get_token_symbol(&info.token1),
+            info,
+            volumes,
+            liquidity,
+            active_liquidity,
+            window_yield,
+            windows,
+            price,
+        }))
+    }
+
+    pub async fn loop_sort_yield(self: Arc<Self>) -> LoopResult {
+        let pools = self.get_top_pools(&YieldFilter::default())?;
+        log::info!(
+            "[{}] Top {} pools by fee rate per hour over {}:",
+            self.config.name,
+            pools.len(),
+            VolumeWindow::get_default().name
+        );
+        for pool in pools {
+            let window_yield = &pool.window_yield;
             log::info!(
-                "Pool: {}, Volume: {:.2}, Liquidity: {:.2}, APH: {:.6}",
-                pool,
-                total_volume,
-                liquidity,
-                fee_rate_per_hour
+                "[{}] Pool: {} {}/{}, Volume: {:.2}, Liquidity: {:.2}, APH: {:.6}, NetAPH: {:.6}, RealizedAPH: {:?}, AdjustedAPH: {:?}, Volatility: {:?}, OrganicAPH: {:.6}, TopActorShare: {:?}, APR: {:.4}, APY: {:.4}, NetAPY: {:.4}, ActiveLiquidity: {:.2}, ActiveAPH: {:.6}",
+                self.config.name,
+                pool.pool,
+                pool.token0_symbol,
+                pool.token1_symbol,
+                window_yield.volume,
+                pool.liquidity,
+                window_yield.aph,
+                window_yield.net_aph,
+                window_yield.realized_aph,
+                window_yield.adjusted_aph,
+                window_yield.volatility,
+                window_yield.organic_aph,
+                window_yield.top_actor_share,
+                window_yield.annual.apr,
+                window_yield.annual.apy,
+                window_yield.annual.net_apy,
+                pool.active_liquidity,
+                window_yield.active_aph
             );
         }
 